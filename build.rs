@@ -1,4 +1,7 @@
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 fn main() {
     let target = env::var("TARGET").unwrap();
@@ -9,5 +12,77 @@ fn main() {
     } else if target.contains("riscv64") {
         println!("cargo:rustc-cfg=riscv");
         println!("cargo:rustc-cfg=riscv64");
+        user_programs();
     }
 }
+
+/// assemble and link user/*.S into OUT_DIR, along with a table of them for the kernel to include
+///
+/// without llvm-mc or rust-lld the kernel is built without user programs
+fn user_programs() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=user/user.ld");
+    println!("cargo:rerun-if-env-changed=LLVM_MC");
+    println!("cargo:rerun-if-env-changed=RUST_LLD");
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let mc = env::var("LLVM_MC").unwrap_or_else(|_| String::from("llvm-mc"));
+    let ld = env::var("RUST_LLD").unwrap_or_else(|_| rust_lld());
+
+    let mut sources: Vec<PathBuf> = fs::read_dir("user")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "S"))
+        .collect();
+    sources.sort();
+    let mut table = String::from("&[\n");
+    for src in sources.iter() {
+        println!("cargo:rerun-if-changed={}", src.display());
+        let name = src.file_stem().unwrap().to_str().unwrap();
+        let obj = out.join(format!("{}.o", name));
+        let bin = out.join(name);
+        let built = run(Command::new(&mc)
+            .args(&["--triple=riscv64", "-mattr=+m,+a,+c", "-filetype=obj", "-o"])
+            .arg(&obj)
+            .arg(src))
+            && run(Command::new(&ld)
+                .args(&["-flavor", "gnu", "-T", "user/user.ld", "-o"])
+                .arg(&bin)
+                .arg(&obj));
+        if !built {
+            println!(
+                "cargo:warning=cannot build {} with {} and {}, leaving out the user programs",
+                src.display(),
+                mc,
+                ld
+            );
+            return;
+        }
+        table += &format!("    ({:?}, include_bytes!({:?})),\n", name, bin);
+    }
+    table += "]\n";
+    fs::write(out.join("programs.rs"), table).unwrap();
+    println!("cargo:rustc-cfg=user_programs");
+}
+
+/// the rust-lld that comes with the toolchain, cargo-binutils puts a proxy of the same name on PATH
+fn rust_lld() -> String {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let host = env::var("HOST").unwrap();
+    Command::new(rustc)
+        .args(&["--print", "sysroot"])
+        .output()
+        .ok()
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|sysroot| {
+            Path::new(sysroot.trim())
+                .join("lib/rustlib")
+                .join(host)
+                .join("bin/rust-lld")
+        })
+        .filter(|lld| lld.exists())
+        .map_or(String::from("rust-lld"), |lld| lld.display().to_string())
+}
+
+fn run(cmd: &mut Command) -> bool {
+    cmd.status().map_or(false, |status| status.success())
+}
@@ -0,0 +1,38 @@
+use crate::config::*;
+
+/// give up after this many frames, in case the frame chain is corrupted
+const MAX_DEPTH: usize = 32;
+
+/// walk the frame pointer chain starting at `fp` and print the return addresses
+///
+/// relies on `-C force-frame-pointers=yes`:
+/// `ra` is saved at `fp - 8` and the caller's `fp` at `fp - 16`
+pub(crate) fn print(mut fp: usize) {
+    println!("backtrace:");
+    for depth in 0..MAX_DEPTH {
+        if !is_valid(fp) {
+            break;
+        }
+        let ra = unsafe { *((fp - 8) as *const usize) };
+        fp = unsafe { *((fp - 16) as *const usize) };
+        if ra == 0 {
+            break;
+        }
+        println!("  #{:<2} {:#018x}", depth, ra);
+    }
+}
+
+/// print the backtrace of the caller
+#[inline(always)]
+pub(crate) fn trace() {
+    let fp: usize;
+    unsafe { asm!("mv $0, s0" : "=r"(fp) ::: "volatile") }
+    print(fp);
+}
+
+/// only follow pointers into the linearly mapped kernel space
+fn is_valid(fp: usize) -> bool {
+    fp % 8 == 0
+        && fp >= KERNEL_BEGIN_VADDR + 16
+        && fp <= PHYSICAL_MEMORY_END + PHYSICAL_MEMORY_OFFSET
+}
@@ -24,6 +24,7 @@ _start:
     # load the address of bootstacktop into sp
     lui sp, %hi(boot_stack_top)
     # call rust_main
+    # a0 (hart id) and a1 (device tree) from SBI are left untouched as its arguments
     lui t0, %hi(rust_main)
     addi t0, t0, %lo(rust_main)
     jalr t0
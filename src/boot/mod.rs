@@ -2,8 +2,11 @@ use crate::config::*;
 global_asm!(include_str!("entry64.asm"));
 
 #[no_mangle]
-extern "C" fn rust_main() -> ! {
-    println!("+++ booting kernel +++");
+extern "C" fn rust_main(hartid: usize, dtb: usize) -> ! {
+    println!("+++ booting kernel on hart {} +++", hartid);
+    crate::cpu::init(hartid);
+    crate::time::init(dtb);
+    crate::cmdline::init(dtb);
     // extern "C" {
     //     fn end();
     //     fn _start();
@@ -25,7 +28,10 @@ extern "C" fn rust_main() -> ! {
 
     crate::trap::init();
     crate::mem::init();
+    crate::time::init_wall();
+    crate::trap::breakpoint::test();
     crate::thread::init();
+    crate::process::test();
     crate::trap::timer::init();
     crate::thread::run();
 
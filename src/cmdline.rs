@@ -0,0 +1,44 @@
+//! the kernel command line, `/chosen/bootargs` in the device tree
+//!
+//! options are separated by spaces and look like `key=value` or just `key`
+
+use crate::fdt::Fdt;
+
+/// longer command lines are truncated
+const MAX_LEN: usize = 256;
+
+static mut BUF: [u8; MAX_LEN] = [0; MAX_LEN];
+static mut LEN: usize = 0;
+
+/// keep a copy, the device tree will not survive the frame allocator
+pub(crate) fn init(dtb: usize) {
+    let args = unsafe { Fdt::new(dtb) }
+        .and_then(|fdt| fdt.prop("/chosen", "bootargs"))
+        // a null terminated string
+        .map(|args| args.split(|b| *b == 0).next().unwrap_or(&[]))
+        .unwrap_or(&[]);
+    let len = args.len().min(MAX_LEN);
+    unsafe {
+        BUF[..len].copy_from_slice(&args[..len]);
+        LEN = len;
+    }
+    println!("kernel command line: {:?}", get());
+}
+
+pub(crate) fn get() -> &'static str {
+    unsafe { core::str::from_utf8(&BUF[..LEN]).unwrap_or("") }
+}
+
+/// value of the last `key=value` option, an empty string for a bare `key`
+pub(crate) fn option(key: &str) -> Option<&'static str> {
+    get()
+        .split_whitespace()
+        .filter_map(|opt| {
+            let mut kv = opt.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(k), value) if k == key => Some(value.unwrap_or("")),
+                _ => None,
+            }
+        })
+        .last()
+}
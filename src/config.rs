@@ -6,7 +6,9 @@ pub const PHYSICAL_MEMORY_END: usize = PHYSICAL_MEMORY_BEGIN + MAX_PHYSICAL_MEMO
 
 pub const KERNEL_BEGIN_PADDR: usize = 0x8020_0000;
 pub const KERNEL_BEGIN_VADDR: usize = 0xffff_ffff_c020_0000;
-pub const KERNEL_HEAP_SIZE: usize = 0x80_0000;
+// room for 64 kernel stacks, the in-kernel tests alone keep a couple dozen threads alive
+pub const KERNEL_HEAP_SIZE: usize = 0x200_0000;
+// there is no guard page below a kernel stack, so be generous
 pub const KERNEL_STACK_SIZE: usize = 0x8_0000;
 pub const PHYSICAL_MEMORY_OFFSET: usize = KERNEL_BEGIN_VADDR - KERNEL_BEGIN_PADDR;
 
@@ -14,8 +16,26 @@ pub const PAGE_BITS: usize = 12;
 pub const PAGE_SIZE: usize = 1 << PAGE_BITS;
 pub const PAGE_ENTRIES: usize = PAGE_SIZE / size_of::<usize>();
 
+// the lower half of Sv39 belongs to user mode, with the stack at its very top
+pub const USER_STACK_TOP: usize = 0x40_0000_0000;
+pub const USER_STACK_SIZE: usize = 0x1_0000;
+// where signal handlers return to, a guard page below the user stack
+pub const USER_TRAMPOLINE: usize = USER_STACK_TOP - USER_STACK_SIZE - 2 * PAGE_SIZE;
+
+pub const MAX_HARTS: usize = 8;
+// the thread table grows on demand up to this
+pub const MAX_THREADS: usize = 4096;
+
 pub const MAX_PHYSICAL_PAGES: usize = MAX_PHYSICAL_MEMORY / PAGE_SIZE;
 
-// roughly 1 percent of CPU clock
-pub const TIMEBASE: u64 = 100_000;
+// only used when the device tree does not tell, QEMU virt runs at 10MHz
+pub const DEFAULT_TIMEBASE_FREQUENCY: u64 = 10_000_000;
+pub const TICKS_PER_SECOND: u64 = 100;
 pub const TICKS_PER_TIME_SLICE: usize = 10;
+// larger is more important
+pub const DEFAULT_PRIORITY: usize = 8;
+pub const WATCHDOG_TIMEOUT_MS: u64 = 10_000;
+
+pub const GOLDFISH_RTC_PADDR: usize = 0x10_1000;
+// device registers mapped into the kernel space, (paddr, size)
+pub const MMIO_REGIONS: &[(usize, usize)] = &[(GOLDFISH_RTC_PADDR, PAGE_SIZE)];
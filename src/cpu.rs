@@ -0,0 +1,41 @@
+use crate::config::MAX_HARTS;
+
+static mut BOOT_HART: usize = 0;
+
+pub(crate) fn init(hartid: usize) {
+    assert!(hartid < MAX_HARTS, "hart id {} out of range", hartid);
+    unsafe { BOOT_HART = hartid }
+}
+
+/// id of the hart we are running on
+///
+/// only the boot hart runs the kernel for now
+pub(crate) fn id() -> usize {
+    unsafe { BOOT_HART }
+}
+
+/// one `T` for every hart, each working on its own without locking
+///
+/// a hart only gets shared access to its copy, `T` brings its own `Cell`s for whatever changes,
+/// interrupt handlers on the same hart get at the same copy as well
+pub(crate) struct PerCpu<T> {
+    data: [T; MAX_HARTS],
+}
+
+// a hart only reaches into the copy of another through `of`,
+// and each copy is used on whichever hart runs it, so it must be fine to move across
+unsafe impl<T: Send> Sync for PerCpu<T> {}
+
+impl<T> PerCpu<T> {
+    pub(crate) const fn new(data: [T; MAX_HARTS]) -> Self {
+        Self { data }
+    }
+    /// the copy of the current hart
+    pub(crate) fn get(&self) -> &T {
+        &self.data[id()]
+    }
+    /// the copy of `hart`, which may be changing under our feet
+    pub(crate) unsafe fn of(&self, hart: usize) -> &T {
+        &self.data[hart]
+    }
+}
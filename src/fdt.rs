@@ -0,0 +1,149 @@
+//! just enough of a flattened device tree parser to look up a few properties
+
+use crate::config::PHYSICAL_MEMORY_OFFSET;
+
+const MAGIC: u32 = 0xd00d_feed;
+
+const BEGIN_NODE: u32 = 1;
+const END_NODE: u32 = 2;
+const PROP: u32 = 3;
+const NOP: u32 = 4;
+const END: u32 = 9;
+
+/// deeper nodes are skipped
+const MAX_DEPTH: usize = 16;
+
+/// the structure block does not nest properly or has an unknown token
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Corrupted;
+
+pub(crate) struct Fdt {
+    structs: usize,
+    strings: usize,
+}
+
+impl Fdt {
+    /// `pa` is the physical address of the blob handed over by SBI in a1,
+    /// which is read through the linear mapping
+    pub(crate) unsafe fn new(pa: usize) -> Option<Self> {
+        if pa == 0 {
+            return None;
+        }
+        let va = pa + PHYSICAL_MEMORY_OFFSET;
+        if read_u32(va) != MAGIC {
+            return None;
+        }
+        Some(Self {
+            structs: va + read_u32(va + 8) as usize,
+            strings: va + read_u32(va + 12) as usize,
+        })
+    }
+
+    /// visit every property with the index of its node, the path to it, its name and value
+    ///
+    /// stops at the first sign of a corrupted tree, after visiting what came before it
+    pub(crate) fn for_each(
+        &self,
+        mut f: impl FnMut(usize, &[&'static str], &'static str, &'static [u8]),
+    ) -> Result<(), Corrupted> {
+        let mut path = [""; MAX_DEPTH];
+        let mut depth = 0;
+        let mut node = 0;
+        let mut pos = self.structs;
+        loop {
+            let token = unsafe { read_u32(pos) };
+            pos += 4;
+            match token {
+                BEGIN_NODE => {
+                    let name = unsafe { read_str(pos) };
+                    pos = align4(pos + name.len() + 1);
+                    if depth < MAX_DEPTH {
+                        path[depth] = name;
+                    }
+                    depth += 1;
+                    node += 1;
+                }
+                END_NODE => depth = depth.checked_sub(1).ok_or(Corrupted)?,
+                PROP => {
+                    let len = unsafe { read_u32(pos) } as usize;
+                    let name = unsafe { read_str(self.strings + read_u32(pos + 4) as usize) };
+                    let value = unsafe { core::slice::from_raw_parts((pos + 8) as *const u8, len) };
+                    pos = align4(pos + 8 + len);
+                    if depth <= MAX_DEPTH {
+                        f(node, &path[..depth], name, value);
+                    }
+                }
+                NOP => {}
+                END => return Ok(()),
+                _ => return Err(Corrupted),
+            }
+        }
+    }
+
+    /// value of property `name` of the node at `path`, like "/cpus"
+    pub(crate) fn prop(&self, path: &str, name: &str) -> Option<&'static [u8]> {
+        let mut found = None;
+        self.for_each(|_, nodes, prop, value| {
+            // the root node has an empty name
+            if found.is_none()
+                && prop == name
+                && nodes
+                    .iter()
+                    .skip(1)
+                    .copied()
+                    .eq(path.split('/').filter(|s| !s.is_empty()))
+            {
+                found = Some(value);
+            }
+        })
+        .ok()?;
+        found
+    }
+
+    /// value of property `name` of the first node compatible with `compat`
+    pub(crate) fn compatible_prop(&self, compat: &str, name: &str) -> Option<&'static [u8]> {
+        let mut node = None;
+        self.for_each(|idx, _, prop, value| {
+            if node.is_none()
+                && prop == "compatible"
+                && value.split(|b| *b == 0).any(|s| s == compat.as_bytes())
+            {
+                node = Some(idx);
+            }
+        })
+        .ok()?;
+        let node = node?;
+        let mut found = None;
+        self.for_each(|idx, _, prop, value| {
+            if idx == node && prop == name {
+                found = Some(value);
+            }
+        })
+        .ok()?;
+        found
+    }
+}
+
+/// a big endian integer property of one or two cells
+pub(crate) fn to_int(value: &[u8]) -> Option<u64> {
+    match value.len() {
+        4 | 8 => Some(value.iter().fold(0, |acc, b| acc << 8 | u64::from(*b))),
+        _ => None,
+    }
+}
+
+fn align4(pos: usize) -> usize {
+    (pos + 3) & !3
+}
+
+unsafe fn read_u32(addr: usize) -> u32 {
+    u32::from_be(core::ptr::read_volatile(addr as *const u32))
+}
+
+unsafe fn read_str(addr: usize) -> &'static str {
+    let mut len = 0;
+    while *((addr + len) as *const u8) != 0 {
+        len += 1;
+    }
+    core::str::from_utf8_unchecked(core::slice::from_raw_parts(addr as *const u8, len))
+}
@@ -3,6 +3,7 @@ use core::panic::PanicInfo;
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     println!("{}", info);
+    crate::backtrace::trace();
     loop {}
 }
 
@@ -2,8 +2,10 @@
 #![no_std]
 #![feature(asm)]
 #![feature(const_fn)]
+#![feature(const_in_array_repeat_expressions)]
 #![feature(global_asm)]
 #![feature(naked_functions)]
+#![feature(thread_local)]
 #![feature(alloc_error_handler)]
 
 //! An OS in rust!
@@ -16,10 +18,18 @@ extern crate alloc;
 #[macro_use]
 pub mod io;
 
+mod backtrace;
 mod boot;
+mod cmdline;
 mod config;
+mod cpu;
+mod fdt;
 mod lang_item;
 mod mem;
+mod process;
 mod sbi;
+mod sync;
+mod syscall;
 mod thread;
+pub mod time;
 mod trap;
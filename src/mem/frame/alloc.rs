@@ -1,8 +1,9 @@
 use crate::config::*;
+use crate::sync::SpinMutex;
 use core::mem::size_of;
-use spin::Mutex;
 
-pub static FRAME_ALLOCATOR: Mutex<SegmentTreeAllocator> = Mutex::new(SegmentTreeAllocator::new());
+pub static FRAME_ALLOCATOR: SpinMutex<SegmentTreeAllocator> =
+    SpinMutex::new(SegmentTreeAllocator::new());
 
 // range update/query
 pub struct SegmentTreeAllocator {
@@ -1,11 +1,31 @@
 extern crate alloc;
 use crate::config::KERNEL_HEAP_SIZE;
+use crate::sync::IrqMutex;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
-use buddy_system_allocator::LockedHeap;
+use buddy_system_allocator::Heap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::NonNull;
+
+/// the buddy allocator with interrupts and preemption off while it is locked,
+/// neither the tick nor an interrupt handler that allocates can get in the way
+struct KernelHeap(IrqMutex<Heap>);
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0
+            .lock()
+            .alloc(layout)
+            .ok()
+            .map_or(core::ptr::null_mut(), |allocation| allocation.as_ptr())
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout)
+    }
+}
 
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::new();
+static HEAP_ALLOCATOR: KernelHeap = KernelHeap(IrqMutex::new(Heap::empty()));
 
 #[alloc_error_handler]
 fn alloc_error_handler(_: core::alloc::Layout) -> ! {
@@ -16,6 +36,7 @@ pub(crate) fn init() {
     static mut HEAP: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
     unsafe {
         HEAP_ALLOCATOR
+            .0
             .lock()
             .init(HEAP.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
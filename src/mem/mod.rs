@@ -1,11 +1,16 @@
 use crate::config::*;
+use crate::mem::page::entry::EF;
+use crate::mem::page::table::PageTable;
 use crate::mem::set::MemSet;
 
 mod addr;
 mod frame;
 mod heap;
 pub(crate) mod page;
-mod set;
+pub(crate) mod set;
+
+/// the address space of the kernel itself, which every other one is a copy of
+static mut KERNEL: Option<MemSet<'static>> = None;
 
 pub fn init() {
     println!("+++ setting up physical memory +++");
@@ -33,5 +38,37 @@ pub fn init() {
     );
     unsafe {
         memset.activate();
+        KERNEL = Some(memset);
+    }
+}
+
+/// leave whatever address space we are in for the kernel's own
+pub(crate) fn activate_kernel() {
+    unsafe {
+        KERNEL
+            .as_ref()
+            .expect("kernel memory set not initialized")
+            .activate();
+    }
+}
+
+/// overwrite kernel text at `va` with `bytes`
+///
+/// the text is mapped R-X, so the page is made writable for the duration of the write.
+/// the bytes must not cross a page boundary
+pub(crate) unsafe fn patch_text(va: usize, bytes: &[u8]) {
+    let mut tbl = PageTable::current();
+    let mut entry = tbl
+        .entry(va.into())
+        .unwrap_or_else(|| panic!("patching unmapped text {:#x}", va));
+    let flags = entry.pte.flags();
+    entry.pte.flags_mut().insert(EF::WRITABLE);
+    entry.flush();
+    for (i, byte) in bytes.iter().enumerate() {
+        core::ptr::write_volatile((va + i) as *mut u8, *byte);
     }
+    *entry.pte.flags_mut() = flags;
+    entry.flush();
+    // make the new instructions visible to instruction fetch
+    asm!("fence.i"::::"volatile");
 }
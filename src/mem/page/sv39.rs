@@ -71,6 +71,22 @@ impl<'a> Sv39PageTable<'a> {
             unsafe { entry.frame().as_kernel_mut(self.linear_offset) };
         Ok(p1_table)
     }
+
+    /// Free the frames of all intermediate tables, the root table is left to its owner
+    ///
+    /// The leaf mappings are not touched, whoever mapped them should have unmapped them by now
+    pub fn free_tables(&mut self) {
+        let offset = self.linear_offset;
+        for p3_entry in self.root_table.iter_mut().filter(|e| !e.is_unused()) {
+            let p2_table: &mut PageTableBase = unsafe { p3_entry.frame().as_kernel_mut(offset) };
+            for p2_entry in p2_table.iter_mut().filter(|e| !e.is_unused()) {
+                frame::dealloc(p2_entry.frame());
+                p2_entry.set_unused();
+            }
+            frame::dealloc(p3_entry.frame());
+            p3_entry.set_unused();
+        }
+    }
 }
 
 #[cfg(riscv64)]
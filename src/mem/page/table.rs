@@ -28,6 +28,18 @@ impl<'a> PageTable<'a> {
             root_frame: frame,
         }
     }
+    /// wrap the page table currently loaded in satp
+    ///
+    /// only valid after the kernel has switched away from the boot page table,
+    /// whose 1GiB huge page cannot be walked as a three level table
+    pub unsafe fn current() -> Self {
+        let frame = Frame::from_ppn(Self::active().ppn());
+        let table: &mut PageTableBase = frame.as_kernel_mut(PHYSICAL_MEMORY_OFFSET);
+        Self {
+            sv39: Sv39PageTable::new(table, PHYSICAL_MEMORY_OFFSET),
+            root_frame: frame,
+        }
+    }
 
     pub fn map(&mut self, va: VirtAddr, pa: PhysAddr) {
         let flags = EF::VALID | EF::READABLE | EF::WRITABLE;
@@ -37,16 +49,29 @@ impl<'a> PageTable<'a> {
             .ok()
             .unwrap_or_else(|| panic!("map failed {:#x} {:#x}", va.as_usize(), pa.as_usize()));
     }
-    pub fn unmap(&mut self, va: VirtAddr) {
+    /// the frame that was mapped at `va` is handed back, freeing it is up to the caller
+    pub fn unmap(&mut self, va: VirtAddr) -> Frame {
         let page = Page(va);
         self.sv39
             .unmap(page)
             .map(|(frame, flush)| {
-                crate::mem::frame::dealloc(frame);
                 flush.flush();
+                frame
             })
             .ok()
-            .expect("unmap failed");
+            .expect("unmap failed")
+    }
+    /// free the frames of the table itself, all the mappings must have been removed
+    ///
+    /// must not be the active page table, nor used afterwards
+    pub unsafe fn destroy(&mut self) {
+        assert_ne!(
+            Self::active().bits(),
+            self.satp(),
+            "destroying the active page table"
+        );
+        self.sv39.free_tables();
+        crate::mem::frame::dealloc(self.root_frame);
     }
     pub fn entry(&mut self, va: VirtAddr) -> Option<PageEntry> {
         let page = Page(va);
@@ -31,6 +31,14 @@ impl MemArea {
             self.handler.map(tbl, page.start_address(), self.attrib);
         }
     }
+    /// the same area in `dst`, a copy of what it is in `src`
+    pub(crate) fn fork(&self, src: &mut PageTable, dst: &mut PageTable) -> Self {
+        for page in PageRange::new(self.begin, self.end) {
+            self.handler
+                .fork(src, dst, page.start_address(), self.attrib);
+        }
+        Self::new(self.begin, self.end, self.handler.box_clone(), self.attrib)
+    }
     pub(crate) fn unmap(&self, tbl: &mut PageTable) {
         for page in PageRange::new(self.begin, self.end) {
             self.handler.unmap(tbl, page.start_address());
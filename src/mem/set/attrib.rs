@@ -40,5 +40,6 @@ impl MemAttrib {
         flags.set(EF::READABLE, self.readable);
         flags.set(EF::WRITABLE, self.writable);
         flags.set(EF::EXECUTABLE, self.executable);
+        flags.set(EF::USER, self.user);
     }
 }
@@ -1,11 +1,17 @@
+use crate::config::*;
 use crate::mem::addr::VirtAddr;
 use crate::mem::frame;
 use crate::mem::page::table::PageTable;
 use crate::mem::set::attrib::MemAttrib;
+use alloc::boxed::Box;
 
-pub(crate) trait MemHandler {
+/// `Send` as the memory set of a process can be torn down by any thread
+pub(crate) trait MemHandler: Send {
     fn map(&self, tbl: &mut PageTable, va: VirtAddr, attr: MemAttrib);
     fn unmap(&self, tbl: &mut PageTable, va: VirtAddr);
+    /// map `va` in `dst` as it is in `src`, with its own copy of the contents unless shared
+    fn fork(&self, src: &mut PageTable, dst: &mut PageTable, va: VirtAddr, attr: MemAttrib);
+    fn box_clone(&self) -> Box<dyn MemHandler>;
 }
 
 pub(crate) struct Linear {
@@ -26,8 +32,17 @@ impl MemHandler for Linear {
         )
     }
 
+    /// the frame belongs to the kernel, leave it alone
     fn unmap(&self, tbl: &mut PageTable, va: VirtAddr) {
-        tbl.unmap(va)
+        tbl.unmap(va);
+    }
+
+    fn fork(&self, _src: &mut PageTable, dst: &mut PageTable, va: VirtAddr, attr: MemAttrib) {
+        self.map(dst, va, attr)
+    }
+
+    fn box_clone(&self) -> Box<dyn MemHandler> {
+        Box::new(Self::new(self.offset))
     }
 }
 
@@ -40,11 +55,29 @@ impl ByFrame {
 impl MemHandler for ByFrame {
     fn map(&self, tbl: &mut PageTable, va: VirtAddr, attr: MemAttrib) {
         let frame = frame::alloc().expect("frame allocation failed");
+        // whatever the last owner left there is none of the new one's business
+        let page: &mut [u8; PAGE_SIZE] = unsafe { frame.as_kernel_mut(PHYSICAL_MEMORY_OFFSET) };
+        *page = [0; PAGE_SIZE];
         tbl.map(va, frame.start_address());
         attr.apply(tbl.entry(va).expect("cannot find mapped entry"));
     }
 
     fn unmap(&self, tbl: &mut PageTable, va: VirtAddr) {
-        tbl.unmap(va)
+        frame::dealloc(tbl.unmap(va));
+    }
+
+    fn fork(&self, src: &mut PageTable, dst: &mut PageTable, va: VirtAddr, attr: MemAttrib) {
+        self.map(dst, va, attr);
+        let from = src.entry(va).expect("forking an unmapped page").pte.addr();
+        let to = dst.entry(va).expect("cannot find mapped entry").pte.addr();
+        unsafe {
+            let from: &[u8; PAGE_SIZE] = from.as_kernel_mut(PHYSICAL_MEMORY_OFFSET);
+            let to: &mut [u8; PAGE_SIZE] = to.as_kernel_mut(PHYSICAL_MEMORY_OFFSET);
+            *to = *from;
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn MemHandler> {
+        Box::new(Self::new())
     }
 }
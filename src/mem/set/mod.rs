@@ -6,6 +6,7 @@ use crate::mem::set::attrib::MemAttrib;
 use crate::mem::set::handler::MemHandler;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
+use core::cmp::min;
 
 mod area;
 pub(crate) mod attrib;
@@ -14,13 +15,29 @@ pub(crate) mod handler;
 pub(crate) struct MemSet<'a> {
     areas: Vec<MemArea>,
     tbl: PageTable<'a>,
+    /// the first so many areas are the kernel, the same in every memory set
+    kernel: usize,
 }
 impl<'a> MemSet<'a> {
     pub(crate) fn new() -> Self {
         let tbl = PageTable::bare();
         let areas: Vec<MemArea> = Vec::new();
-        let mut set = Self { tbl, areas };
+        let mut set = Self {
+            tbl,
+            areas,
+            kernel: 0,
+        };
         set.kmap();
+        set.kernel = set.areas.len();
+        set
+    }
+    /// a deep copy, for `fork`
+    pub(crate) fn fork(&mut self) -> MemSet<'a> {
+        let mut set = Self::new();
+        for area in self.areas[self.kernel..].iter() {
+            let copy = area.fork(&mut self.tbl, &mut set.tbl);
+            set.areas.push(copy);
+        }
         set
     }
     pub(crate) fn push(
@@ -58,6 +75,79 @@ impl<'a> MemSet<'a> {
     pub(crate) unsafe fn activate(&self) {
         self.tbl.activate()
     }
+    pub(crate) fn satp(&self) -> usize {
+        self.tbl.satp()
+    }
+    /// copy `data` to `va`, through the kernel's view of the frames
+    ///
+    /// so that it works whether this is the active address space or not,
+    /// every page written to must be mapped already
+    pub(crate) fn write(&mut self, va: usize, data: &[u8]) {
+        let mut done = 0;
+        while done < data.len() {
+            let va = va + done;
+            let entry = self
+                .tbl
+                .entry(va.into())
+                .filter(|e| e.valid())
+                .unwrap_or_else(|| panic!("writing to unmapped {:#x}", va));
+            let offset = va % PAGE_SIZE;
+            let len = min(PAGE_SIZE - offset, data.len() - done);
+            let dst = entry.pte.addr().as_usize() + offset + PHYSICAL_MEMORY_OFFSET;
+            unsafe {
+                core::ptr::copy_nonoverlapping(data[done..].as_ptr(), dst as *mut u8, len);
+            }
+            done += len;
+        }
+    }
+    /// copy from `va` into `data` as user mode would read it, through the kernel's view
+    ///
+    /// false if some page is not mapped readable for user mode
+    pub(crate) fn read_user(&mut self, va: usize, data: &mut [u8]) -> bool {
+        self.user_pages(va, data.len(), false, |src, done, len| unsafe {
+            core::ptr::copy_nonoverlapping(src as *const u8, data[done..].as_mut_ptr(), len);
+        })
+    }
+    /// copy `data` to `va` as user mode would write it, through the kernel's view
+    ///
+    /// false if some page is not mapped writable for user mode,
+    /// the pages in front of it have been written already
+    pub(crate) fn write_user(&mut self, va: usize, data: &[u8]) -> bool {
+        self.user_pages(va, data.len(), true, |dst, done, len| unsafe {
+            core::ptr::copy_nonoverlapping(data[done..].as_ptr(), dst as *mut u8, len);
+        })
+    }
+    /// call `f` with the kernel address, offset and length of each piece of
+    /// `[va, va + len)` within a single page
+    ///
+    /// stops with false at the first page user mode may not read, or write if `write`
+    fn user_pages(
+        &mut self,
+        va: usize,
+        len: usize,
+        write: bool,
+        mut f: impl FnMut(usize, usize, usize),
+    ) -> bool {
+        match va.checked_add(len) {
+            Some(end) if end <= USER_STACK_TOP => {}
+            _ => return false,
+        }
+        let mut done = 0;
+        while done < len {
+            let va = va + done;
+            let offset = va % PAGE_SIZE;
+            let kva = match self.tbl.entry(va.into()) {
+                Some(e) if e.valid() && e.user() && e.readable() && (e.writable() || !write) => {
+                    e.pte.addr().as_usize() + offset + PHYSICAL_MEMORY_OFFSET
+                }
+                _ => return false,
+            };
+            let piece = min(PAGE_SIZE - offset, len - done);
+            f(kva, done, piece);
+            done += piece;
+        }
+        true
+    }
     pub(crate) fn is_overlap(&self, begin: VirtAddr, end: VirtAddr) -> bool {
         0 < self
             .areas
@@ -137,5 +227,34 @@ impl<'a> MemSet<'a> {
             handler::Linear::new(PHYSICAL_MEMORY_OFFSET),
             attrib::MemAttrib::new().readable(true).writable(true),
         );
+        // memory mapped devices RW-
+        for &(paddr, size) in MMIO_REGIONS {
+            println!(
+                "[{:#x}, {:#x}) RW- mmio",
+                paddr + PHYSICAL_MEMORY_OFFSET,
+                paddr + size + PHYSICAL_MEMORY_OFFSET
+            );
+            self.push(
+                (paddr + PHYSICAL_MEMORY_OFFSET).into(),
+                (paddr + size + PHYSICAL_MEMORY_OFFSET).into(),
+                handler::Linear::new(PHYSICAL_MEMORY_OFFSET),
+                attrib::MemAttrib::new().readable(true).writable(true),
+            );
+        }
+    }
+}
+
+impl Drop for MemSet<'_> {
+    fn drop(&mut self) {
+        // the kernel part is the same everywhere, carry on in the kernel's own page table
+        if PageTable::active().bits() == self.satp() {
+            crate::mem::activate_kernel();
+        }
+        for area in self.areas.iter() {
+            area.unmap(&mut self.tbl);
+        }
+        unsafe {
+            self.tbl.destroy();
+        }
     }
 }
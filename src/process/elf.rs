@@ -0,0 +1,222 @@
+//! just enough of ELF64 to load statically linked RISC-V executables
+
+use crate::config::*;
+use crate::mem::set::attrib::MemAttrib;
+use crate::mem::set::handler::ByFrame;
+use crate::mem::set::MemSet;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ElfError {
+    /// shorter than its headers say
+    Truncated,
+    /// not an ELF file at all
+    BadMagic,
+    /// not a 64-bit little-endian RISC-V executable
+    Unsupported,
+    /// larger in the file than in memory, or reaching out of user space
+    BadSegment,
+    /// two segments share a page
+    Overlap,
+    /// argv and envp do not fit on the user stack
+    ArgsTooLong,
+}
+
+const ET_EXEC: u16 = 2;
+const EM_RISCV: u16 = 0xf3;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+/// size of a program header
+const PHENT: usize = 56;
+
+// auxiliary vector entries
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+
+/// `len` bytes at `off`, the offsets come from the file and cannot be trusted
+fn field(data: &[u8], off: usize, len: usize) -> Result<&[u8], ElfError> {
+    off.checked_add(len)
+        .and_then(|end| data.get(off..end))
+        .ok_or(ElfError::Truncated)
+}
+fn u16_at(data: &[u8], off: usize) -> Result<u16, ElfError> {
+    Ok(u16::from_le_bytes(field(data, off, 2)?.try_into().unwrap()))
+}
+fn u32_at(data: &[u8], off: usize) -> Result<u32, ElfError> {
+    Ok(u32::from_le_bytes(field(data, off, 4)?.try_into().unwrap()))
+}
+fn usize_at(data: &[u8], off: usize) -> Result<usize, ElfError> {
+    Ok(u64::from_le_bytes(field(data, off, 8)?.try_into().unwrap()) as usize)
+}
+
+/// a program header
+struct Segment {
+    kind: u32,
+    flags: u32,
+    offset: usize,
+    vaddr: usize,
+    filesz: usize,
+    memsz: usize,
+}
+
+pub(crate) struct Elf<'a> {
+    data: &'a [u8],
+    entry: usize,
+    phoff: usize,
+    phentsize: usize,
+    phnum: usize,
+}
+impl<'a> Elf<'a> {
+    pub(crate) fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        if data.get(0..4) != Some(b"\x7fELF") {
+            return Err(ElfError::BadMagic);
+        }
+        // 64-bit, little-endian
+        if data.get(4) != Some(&2) || data.get(5) != Some(&1) {
+            return Err(ElfError::Unsupported);
+        }
+        if u16_at(data, 16)? != ET_EXEC || u16_at(data, 18)? != EM_RISCV {
+            return Err(ElfError::Unsupported);
+        }
+        let elf = Self {
+            data,
+            entry: usize_at(data, 24)?,
+            phoff: usize_at(data, 32)?,
+            phentsize: u16_at(data, 54)? as usize,
+            phnum: u16_at(data, 56)? as usize,
+        };
+        if elf.phentsize < PHENT {
+            return Err(ElfError::Unsupported);
+        }
+        Ok(elf)
+    }
+    pub(crate) fn entry(&self) -> usize {
+        self.entry
+    }
+    fn segment(&self, i: usize) -> Result<Segment, ElfError> {
+        let off = self
+            .phoff
+            .checked_add(i * self.phentsize)
+            .ok_or(ElfError::Truncated)?;
+        Ok(Segment {
+            kind: u32_at(self.data, off)?,
+            flags: u32_at(self.data, off + 4)?,
+            offset: usize_at(self.data, off + 8)?,
+            vaddr: usize_at(self.data, off + 16)?,
+            filesz: usize_at(self.data, off + 32)?,
+            memsz: usize_at(self.data, off + 40)?,
+        })
+    }
+    /// where the program headers end up in memory, if they are loaded at all
+    fn phdr(&self) -> Option<usize> {
+        (0..self.phnum)
+            .filter_map(|i| self.segment(i).ok())
+            .find(|s| {
+                s.kind == PT_LOAD
+                    && s.offset <= self.phoff
+                    && self.phoff < s.offset.saturating_add(s.filesz)
+            })
+            .map(|s| s.vaddr + (self.phoff - s.offset))
+    }
+    /// map the loadable segments into `memset` and copy their contents over
+    ///
+    /// the pages come zeroed, which takes care of the BSS
+    pub(crate) fn load(&self, memset: &mut MemSet) -> Result<(), ElfError> {
+        for i in 0..self.phnum {
+            let seg = self.segment(i)?;
+            if seg.kind != PT_LOAD || seg.memsz == 0 {
+                continue;
+            }
+            let end = seg
+                .vaddr
+                .checked_add(seg.memsz)
+                .filter(|&end| seg.filesz <= seg.memsz && end <= USER_TRAMPOLINE)
+                .ok_or(ElfError::BadSegment)?;
+            let data = field(self.data, seg.offset, seg.filesz)?;
+            let begin = seg.vaddr / PAGE_SIZE * PAGE_SIZE;
+            let end = (end + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+            if memset.is_overlap(begin.into(), end.into()) {
+                return Err(ElfError::Overlap);
+            }
+            let attrib = MemAttrib::new()
+                .readable(seg.flags & PF_R != 0)
+                .writable(seg.flags & PF_W != 0)
+                .executable(seg.flags & PF_X != 0)
+                .user(true);
+            memset.push(begin.into(), end.into(), ByFrame::new(), attrib);
+            memset.write(seg.vaddr, data);
+        }
+        // the text was written as data, make sure instruction fetch sees it
+        unsafe { asm!("fence.i"::::"volatile") };
+        Ok(())
+    }
+    /// map the user stack and lay out argc, argv, envp and auxv on it as the System V ABI says
+    ///
+    /// returns the initial stack pointer, which points at argc
+    pub(crate) fn init_stack(
+        &self,
+        memset: &mut MemSet,
+        argv: &[&str],
+        envp: &[&str],
+    ) -> Result<usize, ElfError> {
+        let bottom = USER_STACK_TOP - USER_STACK_SIZE;
+        // the strings go at the very top, they fit in one page or not at all
+        let size: usize = argv.iter().chain(envp.iter()).map(|s| s.len() + 1).sum();
+        // argc, both lists with their NULLs, at most five auxv entries and AT_NULL
+        let words = 1 + argv.len() + 1 + envp.len() + 1 + 6 * 2;
+        if size + words * 8 + 16 > PAGE_SIZE {
+            return Err(ElfError::ArgsTooLong);
+        }
+        memset.push(
+            bottom.into(),
+            USER_STACK_TOP.into(),
+            ByFrame::new(),
+            MemAttrib::new().readable(true).writable(true).user(true),
+        );
+        let mut sp = USER_STACK_TOP;
+        // the pages are zeroed, the terminating NULs are already there
+        let mut push_strings = |list: &[&str]| -> Vec<usize> {
+            list.iter()
+                .map(|s| {
+                    sp -= s.len() + 1;
+                    memset.write(sp, s.as_bytes());
+                    sp
+                })
+                .collect()
+        };
+        let argv = push_strings(argv);
+        let envp = push_strings(envp);
+        let mut stack = vec![argv.len()];
+        stack.extend(argv);
+        stack.push(0);
+        stack.extend(envp);
+        stack.push(0);
+        if let Some(phdr) = self.phdr() {
+            stack.extend(&[AT_PHDR, phdr]);
+        }
+        stack.extend(&[
+            AT_PHENT,
+            self.phentsize,
+            AT_PHNUM,
+            self.phnum,
+            AT_PAGESZ,
+            PAGE_SIZE,
+            AT_ENTRY,
+            self.entry,
+            AT_NULL,
+            0,
+        ]);
+        let sp = (sp - stack.len() * 8) & !0xf;
+        for (i, word) in stack.iter().enumerate() {
+            memset.write(sp + i * 8, &word.to_le_bytes());
+        }
+        Ok(sp)
+    }
+}
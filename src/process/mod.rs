@@ -0,0 +1,603 @@
+//! processes, each an address space shared by one or more threads
+//!
+//! kernel threads belong to no process,
+//! they run in whichever address space happens to be loaded
+
+use crate::config::PAGE_SIZE;
+use crate::mem::set::attrib::MemAttrib;
+use crate::mem::set::handler::ByFrame;
+use crate::mem::set::MemSet;
+use crate::sync::{IrqMutex, Mutex, Semaphore};
+use crate::thread::{self, ExitCode, JoinHandle, SpawnError, TaskId, Thread, ThreadArgs};
+use crate::trap::{self, Frame};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::{size_of, MaybeUninit};
+use elf::Elf;
+pub(crate) use elf::ElfError;
+use signal::{Signal, Signals, ThreadSignals};
+
+mod elf;
+pub(crate) mod signal;
+
+pub(crate) type Pid = usize;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum LoadError {
+    /// the executable is malformed, or not for us
+    Elf(ElfError),
+    Spawn(SpawnError),
+    /// other threads still run in the address space `exec` would replace
+    Busy,
+}
+impl From<ElfError> for LoadError {
+    fn from(err: ElfError) -> Self {
+        LoadError::Elf(err)
+    }
+}
+impl From<SpawnError> for LoadError {
+    fn from(err: SpawnError) -> Self {
+        LoadError::Spawn(err)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum WaitError {
+    /// no such child, or none at all
+    NoChildren,
+    /// a signal came in first
+    Interrupted,
+}
+
+/// how a process ended
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ExitStatus {
+    /// its last thread exited with this code
+    Exited(ExitCode),
+    /// taken down by a signal
+    Signaled(Signal),
+}
+
+/// user memory the current process may not access that way, or no process at all
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct BadAddress;
+
+/// the executables build.rs made from user/, there being no file system to load them from
+#[cfg(user_programs)]
+static PROGRAMS: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/programs.rs"));
+/// build.rs could not find the tools to make them
+#[cfg(not(user_programs))]
+static PROGRAMS: &[(&str, &[u8])] = &[];
+
+pub(crate) fn program(name: &str) -> Option<&'static [u8]> {
+    PROGRAMS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, elf)| *elf)
+}
+
+pub(crate) struct Process {
+    pid: Pid,
+    name: String,
+    /// None if it was started by a kernel thread, or its parent has exited
+    parent: Option<Pid>,
+    children: Vec<Pid>,
+    /// gone once the last thread has exited
+    ///
+    /// with a lock of its own, copying it for `fork` takes too long to hold the table meanwhile
+    memset: Option<Arc<Mutex<MemSet<'static>>>>,
+    /// what loads `memset`, to be had without taking its lock
+    satp: usize,
+    /// each with the signals it blocks or has to take itself
+    threads: BTreeMap<TaskId, ThreadSignals>,
+    /// set when the last thread exits, the process stays around for its parent to see
+    status: Option<ExitStatus>,
+    /// threads blocked in `wait` for one of the children, or for a signal
+    waiters: Vec<TaskId>,
+    signals: Signals,
+}
+
+struct Table {
+    procs: BTreeMap<Pid, Process>,
+    next: Pid,
+}
+impl Table {
+    fn new() -> Self {
+        Self {
+            procs: BTreeMap::new(),
+            next: 1,
+        }
+    }
+    /// forget about `pid` altogether, it must have no threads left
+    fn remove(&mut self, pid: Pid) {
+        if let Some(process) = self.procs.remove(&pid) {
+            assert!(process.threads.is_empty(), "removing a running process");
+            if let Some(parent) = process.parent.and_then(|p| self.procs.get_mut(&p)) {
+                parent.children.retain(|&c| c != pid);
+            }
+            for child in process.children {
+                self.orphan(child);
+            }
+        }
+    }
+    /// the parent of `pid` is gone, nobody is going to wait for it anymore
+    fn orphan(&mut self, pid: Pid) {
+        let process = self
+            .procs
+            .get_mut(&pid)
+            .expect("child of a process is gone");
+        process.parent = None;
+        if process.status.is_some() {
+            self.remove(pid);
+        }
+    }
+    /// the last thread of `pid` has exited with `code`
+    fn exit(&mut self, pid: Pid, code: ExitCode) {
+        let process = self
+            .procs
+            .get_mut(&pid)
+            .expect("exiting a process that is gone");
+        let status = match process.signals.fatal() {
+            Some(sig) => {
+                println!(
+                    "process {} ({}) killed by signal {}",
+                    pid, process.name, sig
+                );
+                ExitStatus::Signaled(sig)
+            }
+            None => {
+                println!(
+                    "process {} ({}) exited, with code {}",
+                    pid, process.name, code
+                );
+                ExitStatus::Exited(code)
+            }
+        };
+        process.status = Some(status);
+        // leaves the address space first if we are in it
+        drop(process.memset.take());
+        let children = core::mem::replace(&mut process.children, Vec::new());
+        let parent = process.parent;
+        for child in children {
+            self.orphan(child);
+        }
+        match parent.and_then(|p| Some((p, self.procs.get_mut(&p)?))) {
+            Some((ppid, parent)) => {
+                for tid in core::mem::replace(&mut parent.waiters, Vec::new()) {
+                    thread::unpark(tid);
+                }
+                // the parent may be on its way out as well, then nobody cares
+                let _ = self.signal(ppid, signal::SIGCHLD);
+            }
+            None => self.remove(pid),
+        }
+    }
+    /// take an exited child of `parent` off the table, `pid` or any if None
+    fn reap(
+        &mut self,
+        parent: Pid,
+        pid: Option<Pid>,
+    ) -> Result<Option<(Pid, ExitStatus)>, WaitError> {
+        let children = &self
+            .procs
+            .get(&parent)
+            .expect("waiting in a process that is gone")
+            .children;
+        if children.is_empty() || pid.map_or(false, |p| !children.contains(&p)) {
+            return Err(WaitError::NoChildren);
+        }
+        let procs = &self.procs;
+        let found = children
+            .iter()
+            .filter(|&&c| pid.map_or(true, |p| p == c))
+            .find_map(|c| Some((*c, procs.get(c)?.status?)));
+        if let Some((child, _)) = found {
+            self.remove(child);
+        }
+        Ok(found)
+    }
+}
+
+/// also reached with interrupts disabled on the way out of a thread
+static TABLE: IrqMutex<Option<Table>> = IrqMutex::new(None);
+
+fn with<R>(f: impl FnOnce(&mut Table) -> R) -> R {
+    let mut table = TABLE.lock();
+    f(table.get_or_insert_with(Table::new))
+}
+
+/// a new process without any thread yet, living in `memset`
+///
+/// it is a child of the current process, if any
+pub(crate) fn create(name: &str, memset: MemSet<'static>) -> Pid {
+    let parent = current();
+    with(|table| {
+        let pid = table.next;
+        table.next += 1;
+        if let Some(parent) = parent.and_then(|p| table.procs.get_mut(&p)) {
+            parent.children.push(pid);
+        }
+        table.procs.insert(
+            pid,
+            Process {
+                pid,
+                name: String::from(name),
+                parent,
+                children: Vec::new(),
+                satp: memset.satp(),
+                memset: Some(Arc::new(Mutex::new(memset))),
+                threads: BTreeMap::new(),
+                status: None,
+                waiters: Vec::new(),
+                signals: Signals::new(),
+            },
+        );
+        pid
+    })
+}
+
+/// start a process with `f` as its first thread, it exits when its last thread does
+pub(crate) fn spawn<F>(name: &str, f: F) -> Result<Pid, SpawnError>
+where
+    F: FnOnce() -> ExitCode + Send + 'static,
+{
+    // an address space with only the kernel in it
+    let pid = create(name, MemSet::new());
+    start_first(pid, |args| args.spawn(f))
+}
+
+/// start a process running the executable `elf`, which sees `argv` and `envp`
+pub(crate) fn load(name: &str, elf: &[u8], argv: &[&str], envp: &[&str]) -> Result<Pid, LoadError> {
+    let elf = Elf::parse(elf)?;
+    // not a process yet, a failure just drops it
+    let (memset, sp) = image(&elf, argv, envp)?;
+    let pid = create(name, memset);
+    let entry = elf.entry();
+    start_first(pid, |args| thread::start(args.create_user(entry, sp))).map_err(LoadError::from)
+}
+
+/// a new address space for `elf`, with its stack set up for `argv` and `envp`
+///
+/// returns it with the initial stack pointer
+fn image(elf: &Elf, argv: &[&str], envp: &[&str]) -> Result<(MemSet<'static>, usize), ElfError> {
+    let mut memset = MemSet::new();
+    // ahead of the segments, which leave instruction fetch in sync
+    signal::map_trampoline(&mut memset);
+    elf.load(&mut memset)?;
+    let sp = elf.init_stack(&mut memset, argv, envp)?;
+    Ok((memset, sp))
+}
+
+/// a copy of the current process, with a copy of the calling thread returning to `tf`
+///
+/// the child sees a0 = 0, this is for system calls from user mode
+pub(crate) fn fork(tf: &Frame) -> Result<Pid, SpawnError> {
+    let me = current().expect("only processes fork");
+    let (name, parent, signals) = with(|table| {
+        let process = table.procs.get(&me)?;
+        Some((
+            process.name.clone(),
+            process.memset.clone()?,
+            process.signals.fork(),
+        ))
+    })
+    .ok_or(SpawnError::NoSuchProcess)?;
+    let memset = parent.lock().fork();
+    let pid = create(&name, memset);
+    with(|table| table.procs.get_mut(&pid).unwrap().signals = signals);
+    let mut tf = tf.clone();
+    tf.x[10] = 0;
+    start_first(pid, |args| thread::start(args.resume_user(tf)))
+}
+
+/// replace the address space of the current process with the executable `elf`,
+/// the calling thread starts over at its entry once it returns to `tf`
+///
+/// on failure the process is left as it was
+pub(crate) fn exec(
+    name: &str,
+    elf: &[u8],
+    argv: &[&str],
+    envp: &[&str],
+    tf: &mut Frame,
+) -> Result<(), LoadError> {
+    let me = current().expect("only processes exec");
+    let elf = Elf::parse(elf)?;
+    let (memset, sp) = image(&elf, argv, envp)?;
+    let satp = memset.satp();
+    let old = with(|table| {
+        let process = table
+            .procs
+            .get_mut(&me)
+            .expect("exec in a process that is gone");
+        if process.threads.len() > 1 {
+            return Err(LoadError::Busy);
+        }
+        process.name = String::from(name);
+        process.signals.exec();
+        process.satp = satp;
+        Ok(process.memset.replace(Arc::new(Mutex::new(memset))))
+    })?;
+    thread::set_satp(satp);
+    // not the active one anymore, and nobody else is in it
+    drop(old);
+    *tf = thread::user_frame(elf.entry(), sp);
+    Ok(())
+}
+
+/// wait for a child of the current process to exit, `pid` or any if None, and reap it
+///
+/// returns its pid and how it ended, or None if `block` is false and none has exited yet
+pub(crate) fn wait(pid: Option<Pid>, block: bool) -> Result<Option<(Pid, ExitStatus)>, WaitError> {
+    let me = current().ok_or(WaitError::NoChildren)?;
+    let tid = thread::current().expect("a process without a running thread");
+    // a child exiting or a signal coming between the look and the park would go unnoticed
+    let sstatus = trap::disable();
+    let result = loop {
+        match with(|table| table.reap(me, pid)) {
+            Ok(None) if block && with(|table| table.interrupted(me, tid)) => {
+                break Err(WaitError::Interrupted)
+            }
+            Ok(None) if block => thread::park(|tid| {
+                with(|table| {
+                    table
+                        .procs
+                        .get_mut(&me)
+                        .expect("waiting in a process that is gone")
+                        .waiters
+                        .push(tid)
+                })
+            }),
+            result => break result,
+        }
+    };
+    trap::restore(sstatus);
+    result
+}
+
+/// the parent of `pid`, None if it has none or there is no such process
+pub(crate) fn parent(pid: Pid) -> Option<Pid> {
+    with(|table| table.procs.get(&pid)?.parent)
+}
+
+/// start the first thread of `pid`, or take the process back down if that fails
+fn start_first(
+    pid: Pid,
+    start: impl FnOnce(ThreadArgs) -> Result<JoinHandle, SpawnError>,
+) -> Result<Pid, SpawnError> {
+    match start_in(pid, start) {
+        Ok(handle) => {
+            handle.detach();
+            Ok(pid)
+        }
+        Err(err) => {
+            with(|table| table.remove(pid));
+            Err(err)
+        }
+    }
+}
+
+/// start another thread in the address space of `pid`
+pub(crate) fn spawn_thread<F>(pid: Pid, f: F) -> Result<JoinHandle, SpawnError>
+where
+    F: FnOnce() -> ExitCode + Send + 'static,
+{
+    start_in(pid, |args| args.spawn(f))
+}
+
+/// `start` a thread with the arguments to make it one of `pid`
+fn start_in(
+    pid: Pid,
+    start: impl FnOnce(ThreadArgs) -> Result<JoinHandle, SpawnError>,
+) -> Result<JoinHandle, SpawnError> {
+    // it must neither run nor let the process exit before it is counted
+    let sstatus = trap::disable();
+    // the signals blocked by whoever creates it, if it is a thread of a process
+    let mask = with(|table| table.mask(current(), thread::current()));
+    let result = with(|table| {
+        table
+            .procs
+            .get(&pid)
+            .filter(|p| p.memset.is_some())
+            .map(|p| (p.name.clone(), p.satp))
+    })
+    .ok_or(SpawnError::NoSuchProcess)
+    .and_then(|(name, satp)| start(Thread::with_args().name(&name).process(pid, satp)));
+    if let Ok(handle) = &result {
+        with(|table| {
+            table
+                .procs
+                .get_mut(&pid)
+                .expect("process vanished with interrupts disabled")
+                .threads
+                .insert(handle.tid(), ThreadSignals::new(mask))
+        });
+    }
+    trap::restore(sstatus);
+    result
+}
+
+/// bookkeeping for a thread of `pid` on its way out, the last one takes the process down
+pub(crate) fn thread_exited(pid: Pid, tid: TaskId, code: ExitCode) {
+    with(|table| {
+        let process = table
+            .procs
+            .get_mut(&pid)
+            .expect("thread of a process that is gone");
+        process.threads.remove(&tid);
+        if process.threads.is_empty() {
+            table.exit(pid, code);
+        }
+    })
+}
+
+/// work on the address space of `pid`, None if it has exited
+///
+/// may sleep for the lock of the address space, the table is not held meanwhile
+pub(crate) fn with_memset<R>(pid: Pid, f: impl FnOnce(&mut MemSet<'static>) -> R) -> Option<R> {
+    let memset = with(|table| table.procs.get(&pid)?.memset.clone())?;
+    let mut memset = memset.lock();
+    Some(f(&mut memset))
+}
+
+/// copy from `va` in the current process into `data`, if user mode may read all of it
+pub(crate) fn copy_from_user(va: usize, data: &mut [u8]) -> Result<(), BadAddress> {
+    let pid = current().ok_or(BadAddress)?;
+    match with_memset(pid, |memset| memset.read_user(va, data)) {
+        Some(true) => Ok(()),
+        _ => Err(BadAddress),
+    }
+}
+
+/// copy `data` to `va` in the current process, if user mode may write all of it
+pub(crate) fn copy_to_user(va: usize, data: &[u8]) -> Result<(), BadAddress> {
+    let pid = current().ok_or(BadAddress)?;
+    match with_memset(pid, |memset| memset.write_user(va, data)) {
+        Some(true) => Ok(()),
+        _ => Err(BadAddress),
+    }
+}
+
+/// a `T` read from `va` in the current process, which need not be aligned
+///
+/// unsafe as whatever user mode left there must be a valid `T`
+pub(crate) unsafe fn read_user<T: Copy>(va: usize) -> Result<T, BadAddress> {
+    let mut value = MaybeUninit::<T>::uninit();
+    let bytes = core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>());
+    copy_from_user(va, bytes)?;
+    Ok(value.assume_init())
+}
+
+/// write `value` to `va` in the current process, which need not be aligned
+pub(crate) fn write_user<T: Copy>(va: usize, value: &T) -> Result<(), BadAddress> {
+    let bytes =
+        unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    copy_to_user(va, bytes)
+}
+
+/// the process of the running thread, None for kernel threads
+pub(crate) fn current() -> Option<Pid> {
+    thread::current_pid()
+}
+
+/// print every process, like `ps`
+pub(crate) fn dump() {
+    println!(
+        "{:>5} {:>5} {:>7} {:<10} NAME",
+        "PID", "PPID", "THREADS", "STATUS"
+    );
+    with(|table| {
+        for process in table.procs.values() {
+            let parent = process
+                .parent
+                .map_or(String::from("-"), |p| format!("{}", p));
+            let status = process
+                .status
+                .map_or(String::from("Running"), |s| format!("{:?}", s));
+            println!(
+                "{:>5} {:>5} {:>7} {:<10} {}",
+                process.pid,
+                parent,
+                process.threads.len(),
+                status,
+                process.name
+            );
+        }
+    })
+}
+
+pub(crate) fn test() {
+    // the same address in every process, backed by a different page in each
+    const VA: usize = 0x1000_0000;
+    fn check() -> ExitCode {
+        let pid = current().expect("running outside of a process");
+        with_memset(pid, |memset| {
+            memset.push(
+                VA.into(),
+                (VA + PAGE_SIZE).into(),
+                ByFrame::new(),
+                MemAttrib::new().readable(true).writable(true),
+            )
+        })
+        .expect("process exited under its own thread");
+        unsafe { core::ptr::write_volatile(VA as *mut usize, pid) };
+        // threads of one process share it
+        let sibling = spawn_thread(pid, || unsafe {
+            core::ptr::read_volatile(VA as *const usize)
+        })
+        .expect("failed to start a second thread");
+        thread::yield_now();
+        assert_eq!(sibling.join(), pid, "address space not shared in a process");
+        assert_eq!(
+            unsafe { core::ptr::read_volatile(VA as *const usize) },
+            pid,
+            "address space shared with another process"
+        );
+        pid
+    }
+    for name in ["as-test-a", "as-test-b"].iter() {
+        spawn(name, check).expect("failed to start a process");
+    }
+    // a signal cuts short nothing but `wait`, a thread blocked elsewhere sleeps on
+    static GATE: Semaphore = Semaphore::new(0);
+    static BLOCKING: Semaphore = Semaphore::new(0);
+    thread::spawn(|| {
+        let pid = spawn("blocked", || {
+            BLOCKING.release();
+            GATE.acquire();
+            0
+        })
+        .expect("failed to start a process");
+        // from here on it is in `acquire` or about to be, whichever the scheduler ran first
+        BLOCKING.acquire();
+        signal::kill(pid, signal::SIGKILL).expect("blocked process gone");
+        thread::yield_now();
+        assert_eq!(
+            with(|table| table.procs.get(&pid).map(|p| p.status)),
+            Some(None),
+            "a blocked thread woken up by a signal"
+        );
+        GATE.release();
+        0
+    })
+    .expect("failed to start the signal test")
+    .detach();
+    let hello = match program("hello") {
+        Some(hello) => hello,
+        None => {
+            println!("no user programs built in, skipping them");
+            return;
+        }
+    };
+    // exits with argc
+    load(
+        "hello",
+        hello,
+        &["hello", "from", "the", "kernel"],
+        &["HOME=/"],
+    )
+    .expect("failed to load hello");
+    assert_eq!(
+        load("garbage", &hello[1..], &[], &[]),
+        Err(LoadError::Elf(ElfError::BadMagic))
+    );
+    // fork, exec and wait from user mode
+    load(
+        "forktest",
+        program("forktest").expect("forktest not built"),
+        &["forktest"],
+        &[],
+    )
+    .expect("failed to load forktest");
+    // handlers, masks, and a child taken down by SIGSEGV
+    load(
+        "sigtest",
+        program("sigtest").expect("sigtest not built"),
+        &["sigtest"],
+        &[],
+    )
+    .expect("failed to load sigtest");
+    dump();
+}
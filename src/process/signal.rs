@@ -0,0 +1,447 @@
+//! POSIX-style signals, sent to a process and taken by one of its threads on its way back to user mode
+//!
+//! the actions and the signals sent to the process as a whole belong to the process,
+//! the mask and faults to each thread
+
+use super::{current, read_user, with, write_user, Pid, Table};
+use crate::config::{PAGE_SIZE, USER_TRAMPOLINE};
+use crate::mem::set::attrib::MemAttrib;
+use crate::mem::set::handler::ByFrame;
+use crate::mem::set::MemSet;
+use crate::thread::{self, TaskId};
+use crate::trap::Frame;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+pub(crate) type Signal = usize;
+
+/// signals are numbered from 1 to NSIG
+pub(crate) const NSIG: usize = 64;
+
+pub(crate) const SIGILL: Signal = 4;
+pub(crate) const SIGTRAP: Signal = 5;
+pub(crate) const SIGBUS: Signal = 7;
+pub(crate) const SIGKILL: Signal = 9;
+pub(crate) const SIGSEGV: Signal = 11;
+pub(crate) const SIGCHLD: Signal = 17;
+const SIGCONT: Signal = 18;
+const SIGSTOP: Signal = 19;
+const SIGTSTP: Signal = 20;
+const SIGTTIN: Signal = 21;
+const SIGTTOU: Signal = 22;
+const SIGURG: Signal = 23;
+const SIGWINCH: Signal = 28;
+
+pub(crate) const SIG_DFL: usize = 0;
+pub(crate) const SIG_IGN: usize = 1;
+/// leave the signal unblocked while its handler runs
+const SA_NODEFER: usize = 0x4000_0000;
+/// back to the default action once delivered
+const SA_RESETHAND: usize = 0x8000_0000;
+
+// how `procmask` changes the mask
+pub(crate) const SIG_BLOCK: usize = 0;
+pub(crate) const SIG_UNBLOCK: usize = 1;
+pub(crate) const SIG_SETMASK: usize = 2;
+
+/// `li a7, 139; ecall`, what a handler returns into
+const TRAMPOLINE: [u32; 2] = [0x08b0_0893, 0x0000_0073];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum SigError {
+    /// not a signal, or one whose action cannot be changed
+    Invalid,
+    /// no such process, or it has exited
+    NoSuchProcess,
+}
+
+/// a set of signals, bit `sig - 1` stands for `sig` as on Linux
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SigSet(pub(crate) u64);
+impl SigSet {
+    pub(crate) const fn empty() -> Self {
+        Self(0)
+    }
+    fn of(sig: Signal) -> Self {
+        Self(1 << (sig - 1))
+    }
+    pub(crate) fn contains(self, sig: Signal) -> bool {
+        self.0 & Self::of(sig).0 != 0
+    }
+    fn insert(&mut self, sig: Signal) {
+        self.0 |= Self::of(sig).0;
+    }
+    fn remove(&mut self, sig: Signal) {
+        self.0 &= !Self::of(sig).0;
+    }
+    /// the lowest signal in the set
+    fn first(self) -> Option<Signal> {
+        match self.0 {
+            0 => None,
+            bits => Some(bits.trailing_zeros() as Signal + 1),
+        }
+    }
+    /// without the signals that can be neither caught nor blocked
+    fn catchable(self) -> Self {
+        Self(self.0 & !(Self::of(SIGKILL).0 | Self::of(SIGSTOP).0))
+    }
+}
+
+/// what to do with a signal, laid out as the `sigaction` Linux takes on RISC-V
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct SigAction {
+    /// SIG_DFL, SIG_IGN or the address of a handler, which gets the signal in a0
+    pub(crate) handler: usize,
+    pub(crate) flags: usize,
+    /// blocked as well while the handler runs
+    pub(crate) mask: SigSet,
+}
+impl SigAction {
+    const DEFAULT: Self = Self {
+        handler: SIG_DFL,
+        flags: 0,
+        mask: SigSet::empty(),
+    };
+    /// dropped as soon as it is sent, rather than left pending
+    fn ignores(&self, sig: Signal) -> bool {
+        match self.handler {
+            SIG_IGN => true,
+            SIG_DFL => ignored_by_default(sig),
+            _ => false,
+        }
+    }
+}
+
+fn valid(sig: Signal) -> bool {
+    1 <= sig && sig <= NSIG
+}
+
+/// the rest terminate the process by default
+///
+/// stopping is not supported, so the signals that would stop or continue a process are ignored too
+fn ignored_by_default(sig: Signal) -> bool {
+    match sig {
+        SIGCHLD | SIGCONT | SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU | SIGURG | SIGWINCH => true,
+        _ => false,
+    }
+}
+
+/// the signal state of a process
+pub(super) struct Signals {
+    actions: [SigAction; NSIG],
+    /// sent to the process, for whichever thread does not block them
+    pending: SigSet,
+    /// the process is going down because of this one, its threads leave as they come by
+    fatal: Option<Signal>,
+}
+impl Signals {
+    pub(super) fn new() -> Self {
+        Self {
+            actions: [SigAction::DEFAULT; NSIG],
+            pending: SigSet::empty(),
+            fatal: None,
+        }
+    }
+    /// what a child gets from `fork`, nothing is pending for it
+    pub(super) fn fork(&self) -> Self {
+        Self {
+            actions: self.actions,
+            ..Self::new()
+        }
+    }
+    /// the handlers are gone with the old program, ignored signals stay ignored
+    pub(super) fn exec(&mut self) {
+        for action in self.actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SigAction::DEFAULT;
+            }
+        }
+    }
+    pub(super) fn fatal(&self) -> Option<Signal> {
+        self.fatal
+    }
+}
+
+/// the signal state of a thread in a process
+#[derive(Debug, Copy, Clone, Default)]
+pub(super) struct ThreadSignals {
+    /// blocked, they stay pending until unblocked
+    mask: SigSet,
+    /// for this thread alone, faults it caused
+    pending: SigSet,
+}
+impl ThreadSignals {
+    pub(super) fn new(mask: SigSet) -> Self {
+        Self {
+            mask,
+            pending: SigSet::empty(),
+        }
+    }
+}
+
+/// what a handler finds at its stack pointer, `sigreturn` takes it back from there
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SigFrame {
+    /// x0 to x31 as they were, sp included
+    regs: [usize; 32],
+    pc: usize,
+    /// the mask before the handler ran
+    mask: SigSet,
+}
+
+impl Table {
+    /// make `sig` pending in `pid` and cut short the `wait` of its threads, 0 only checks for `pid`
+    ///
+    /// `wait` is the only sleep a signal interrupts, threads blocked anywhere else
+    /// take it once they wake up on their own and head back to user mode
+    pub(super) fn signal(&mut self, pid: Pid, sig: Signal) -> Result<(), SigError> {
+        let process = self
+            .procs
+            .get_mut(&pid)
+            .filter(|p| p.status.is_none())
+            .ok_or(SigError::NoSuchProcess)?;
+        if sig == 0 || process.signals.actions[sig - 1].ignores(sig) {
+            return Ok(());
+        }
+        process.signals.pending.insert(sig);
+        for tid in core::mem::replace(&mut process.waiters, Vec::new()) {
+            thread::unpark(tid);
+        }
+        Ok(())
+    }
+    /// take `pid` down with `sig`, every thread of it exits on its way back to user mode
+    ///
+    /// as for `signal`, only threads in `wait` are woken up for it
+    fn kill(&mut self, pid: Pid, sig: Signal) {
+        let process = self
+            .procs
+            .get_mut(&pid)
+            .expect("killing a process that is gone");
+        process.signals.fatal.get_or_insert(sig);
+        for tid in core::mem::replace(&mut process.waiters, Vec::new()) {
+            thread::unpark(tid);
+        }
+    }
+    /// the signals `tid` of `pid` could take right now
+    fn deliverable(&self, pid: Pid, tid: TaskId) -> SigSet {
+        let process = &self.procs[&pid];
+        let thread = &process.threads[&tid];
+        SigSet((process.signals.pending.0 | thread.pending.0) & !thread.mask.0)
+    }
+    /// whether `tid` of `pid` has a signal to take, which interrupts a blocking call
+    pub(super) fn interrupted(&self, pid: Pid, tid: TaskId) -> bool {
+        self.procs[&pid].signals.fatal.is_some() || self.deliverable(pid, tid).first().is_some()
+    }
+    /// the lowest signal `tid` of `pid` can take, with its action and the mask to restore afterwards
+    ///
+    /// it is no longer pending, and blocked as the action says
+    fn take(&mut self, pid: Pid, tid: TaskId) -> Option<(Signal, SigAction, SigSet)> {
+        if let Some(sig) = self.procs[&pid].signals.fatal {
+            return Some((sig, SigAction::DEFAULT, SigSet::empty()));
+        }
+        let sig = self.deliverable(pid, tid).first()?;
+        let process = self.procs.get_mut(&pid).unwrap();
+        let thread = process.threads.get_mut(&tid).unwrap();
+        let mask = thread.mask;
+        if thread.pending.contains(sig) {
+            thread.pending.remove(sig);
+        } else {
+            process.signals.pending.remove(sig);
+        }
+        let action = process.signals.actions[sig - 1];
+        if action.handler != SIG_DFL && action.handler != SIG_IGN {
+            thread.mask.0 |= action.mask.0;
+            if action.flags & SA_NODEFER == 0 {
+                thread.mask.insert(sig);
+            }
+            thread.mask = thread.mask.catchable();
+            if action.flags & SA_RESETHAND != 0 {
+                process.signals.actions[sig - 1] = SigAction::DEFAULT;
+            }
+        }
+        Some((sig, action, mask))
+    }
+    /// the mask of `tid` in `pid`, empty if it is not a thread of a process
+    pub(super) fn mask(&self, pid: Option<Pid>, tid: Option<TaskId>) -> SigSet {
+        match (pid.and_then(|p| self.procs.get(&p)), tid) {
+            (Some(process), Some(tid)) => process
+                .threads
+                .get(&tid)
+                .map_or(SigSet::empty(), |t| t.mask),
+            _ => SigSet::empty(),
+        }
+    }
+}
+
+/// the thread running here, which must belong to a process
+fn me() -> (Pid, TaskId) {
+    let pid = current().expect("signals are for processes");
+    let tid = thread::current().expect("a process without a running thread");
+    (pid, tid)
+}
+
+/// send `sig` to `pid`, 0 just checks that it is there
+pub(crate) fn kill(pid: Pid, sig: Signal) -> Result<(), SigError> {
+    if sig != 0 && !valid(sig) {
+        return Err(SigError::Invalid);
+    }
+    with(|table| table.signal(pid, sig))
+}
+
+/// the action of the current process for `sig`, replaced by `new` if there is one
+pub(crate) fn action(sig: Signal, new: Option<SigAction>) -> Result<SigAction, SigError> {
+    let (pid, _) = me();
+    if !valid(sig) || (new.is_some() && (sig == SIGKILL || sig == SIGSTOP)) {
+        return Err(SigError::Invalid);
+    }
+    with(|table| {
+        let signals = &mut table
+            .procs
+            .get_mut(&pid)
+            .expect("a process without a running thread")
+            .signals;
+        let old = signals.actions[sig - 1];
+        if let Some(new) = new {
+            signals.actions[sig - 1] = new;
+            // no longer to be ignored when it comes, nor when it is already there
+            if new.ignores(sig) {
+                signals.pending.remove(sig);
+            }
+        }
+        Ok(old)
+    })
+}
+
+/// change the mask of the current thread as `how` says, returns the old one
+pub(crate) fn procmask(how: usize, set: Option<SigSet>) -> Result<SigSet, SigError> {
+    let (pid, tid) = me();
+    with(|table| {
+        let thread = table
+            .procs
+            .get_mut(&pid)
+            .and_then(|p| p.threads.get_mut(&tid))
+            .expect("a process without a running thread");
+        let old = thread.mask;
+        if let Some(set) = set {
+            thread.mask = match how {
+                SIG_BLOCK => SigSet(old.0 | set.0),
+                SIG_UNBLOCK => SigSet(old.0 & !set.0),
+                SIG_SETMASK => set,
+                _ => return Err(SigError::Invalid),
+            }
+            .catchable();
+        }
+        Ok(old)
+    })
+}
+
+/// a fault of the current thread, neither blocking nor ignoring it helps
+///
+/// it is taken on the way back to user mode, to the faulting instruction if a handler returns
+pub(crate) fn force(sig: Signal) {
+    let (pid, tid) = me();
+    with(|table| {
+        let process = table
+            .procs
+            .get_mut(&pid)
+            .expect("a process without a running thread");
+        if process.signals.actions[sig - 1].handler == SIG_IGN {
+            process.signals.actions[sig - 1] = SigAction::DEFAULT;
+        }
+        let thread = process.threads.get_mut(&tid).unwrap();
+        thread.mask.remove(sig);
+        thread.pending.insert(sig);
+    })
+}
+
+/// the default action for most signals, the current thread leaves first
+pub(crate) fn terminate(sig: Signal) -> ! {
+    let (pid, _) = me();
+    with(|table| table.kill(pid, sig));
+    thread::exit(128 + sig)
+}
+
+/// on the way back to user mode, act on the signals the current thread can take
+///
+/// for a handler, the registers in `tf` are saved in a frame on the user stack,
+/// and `tf` is changed to call the handler, which returns into the trampoline
+pub(crate) fn deliver(tf: &mut Frame) {
+    let (pid, tid) = match (current(), thread::current()) {
+        (Some(pid), Some(tid)) => (pid, tid),
+        _ => return,
+    };
+    loop {
+        let (sig, action, mask) = match with(|table| table.take(pid, tid)) {
+            Some(taken) => taken,
+            None => return,
+        };
+        match action.handler {
+            _ if action.ignores(sig) => continue,
+            SIG_DFL => terminate(sig),
+            handler => {
+                // below whatever the interrupted code keeps on its stack, 16-byte aligned
+                let sp = match tf.x[2].checked_sub(size_of::<SigFrame>()) {
+                    Some(sp) => sp & !0xf,
+                    None => terminate(SIGSEGV),
+                };
+                let frame = SigFrame {
+                    regs: tf.x,
+                    pc: tf.sepc,
+                    mask,
+                };
+                // no stack to run the handler on
+                if write_user(sp, &frame).is_err() {
+                    terminate(SIGSEGV);
+                }
+                tf.x[1] = USER_TRAMPOLINE;
+                tf.x[2] = sp;
+                tf.x[10] = sig;
+                tf.sepc = handler;
+                return;
+            }
+        }
+    }
+}
+
+/// back from a handler through the trampoline, restore what `deliver` saved at the stack pointer
+///
+/// returns a0 as it was, the system call hands it back
+pub(crate) fn sigreturn(tf: &mut Frame) -> usize {
+    // plain integers, any bits make a valid frame
+    let frame = match unsafe { read_user::<SigFrame>(tf.x[2]) } {
+        Ok(frame) => frame,
+        Err(_) => terminate(SIGSEGV),
+    };
+    // not sstatus, that would let user mode return into the kernel
+    tf.x[1..].copy_from_slice(&frame.regs[1..]);
+    tf.sepc = frame.pc;
+    let (pid, tid) = me();
+    with(|table| {
+        if let Some(thread) = table
+            .procs
+            .get_mut(&pid)
+            .and_then(|p| p.threads.get_mut(&tid))
+        {
+            thread.mask = frame.mask.catchable();
+        }
+    });
+    tf.x[10]
+}
+
+/// map the page handlers return into, the same in every user address space
+///
+/// the code is written as data, a `fence.i` has to follow before it runs
+pub(super) fn map_trampoline(memset: &mut MemSet) {
+    memset.push(
+        USER_TRAMPOLINE.into(),
+        (USER_TRAMPOLINE + PAGE_SIZE).into(),
+        ByFrame::new(),
+        MemAttrib::new().readable(true).executable(true).user(true),
+    );
+    for (i, insn) in TRAMPOLINE.iter().enumerate() {
+        memset.write(USER_TRAMPOLINE + i * 4, &insn.to_le_bytes());
+    }
+}
@@ -0,0 +1,45 @@
+use crate::sync::{Condvar, Mutex};
+
+struct State {
+    count: usize,
+    /// bumped every time the barrier opens, so that it can be reused
+    generation: usize,
+}
+
+/// block until `n` threads have arrived
+pub struct Barrier {
+    n: usize,
+    state: Mutex<State>,
+    cvar: Condvar,
+}
+
+impl Barrier {
+    pub const fn new(n: usize) -> Self {
+        Self {
+            n,
+            state: Mutex::new(State {
+                count: 0,
+                generation: 0,
+            }),
+            cvar: Condvar::new(),
+        }
+    }
+
+    /// true for exactly one of the threads, the last to arrive
+    pub fn wait(&self) -> bool {
+        let mut state = self.state.lock();
+        state.count += 1;
+        if state.count < self.n {
+            let generation = state.generation;
+            let _state = self
+                .cvar
+                .wait_while(state, |state| state.generation == generation);
+            false
+        } else {
+            state.count = 0;
+            state.generation += 1;
+            self.cvar.notify_all();
+            true
+        }
+    }
+}
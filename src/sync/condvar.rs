@@ -0,0 +1,48 @@
+use crate::sync::{MutexGuard, WaitQueue};
+use crate::trap;
+
+/// a condition variable to be used together with `sync::Mutex`
+pub struct Condvar {
+    queue: WaitQueue,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// unlock the mutex and block until notified, then lock it again
+    ///
+    /// wake ups may be spurious, prefer `wait_while`
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        // nobody can notify us between unlocking and going to sleep
+        let sstatus = trap::disable();
+        drop(guard);
+        self.queue.wait();
+        trap::restore(sstatus);
+        mutex.lock()
+    }
+
+    /// block as long as `condition` holds
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) -> bool {
+        self.queue.notify_one()
+    }
+
+    pub fn notify_all(&self) -> usize {
+        self.queue.notify_all()
+    }
+}
@@ -0,0 +1,57 @@
+use crate::sync::{preempt_pending, SpinGuard, SpinMutex};
+use crate::trap;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+/// a spin lock that also keeps interrupts disabled while it is held
+///
+/// for data shared with interrupt handlers, which would otherwise spin forever
+/// on a lock held by the very thread they interrupted
+pub struct IrqMutex<T> {
+    inner: SpinMutex<T>,
+}
+
+pub struct IrqGuard<'a, T> {
+    guard: ManuallyDrop<SpinGuard<'a, T>>,
+    sstatus: usize,
+}
+
+impl<T> IrqMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: SpinMutex::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> IrqGuard<T> {
+        let sstatus = trap::disable();
+        IrqGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            sstatus,
+        }
+    }
+}
+
+impl<T> Deref for IrqGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for IrqGuard<'_, T> {
+    fn drop(&mut self) {
+        // unlock before interrupts can come in again
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        trap::restore(self.sstatus);
+        // the unlock could not switch out with interrupts still off
+        preempt_pending();
+    }
+}
@@ -0,0 +1,103 @@
+//! blocking synchronization primitives for kernel threads
+//!
+//! unlike `spin::Mutex`, waiting threads are put to sleep and leave the CPU to others,
+//! except for `SpinMutex` and `IrqMutex`, which spin with preemption off,
+//! the latter for data shared with interrupt handlers
+
+mod barrier;
+mod condvar;
+mod irq;
+mod mutex;
+mod preempt;
+mod semaphore;
+mod spinlock;
+mod wait;
+
+pub use barrier::Barrier;
+pub use condvar::Condvar;
+pub use irq::{IrqGuard, IrqMutex};
+pub use mutex::{Mutex, MutexGuard};
+pub(crate) use preempt::{preempt_count, preempt_pending};
+pub use preempt::{preempt_disable, PreemptGuard};
+pub use semaphore::Semaphore;
+pub use spinlock::{SpinGuard, SpinMutex};
+pub use wait::WaitQueue;
+
+pub(crate) fn test() {
+    use crate::thread::{self, Thread};
+
+    static COUNTER: Mutex<usize> = Mutex::new(0);
+    static BARRIER: Barrier = Barrier::new(4);
+
+    // spin locks keep preemption off for as long as they are held
+    let lock = IrqMutex::new(());
+    let before = preempt_count();
+    {
+        let _outer = preempt_disable();
+        let _inner = lock.lock();
+        assert_eq!(preempt_count(), before + 2);
+    }
+    assert_eq!(preempt_count(), before);
+
+    // a tick only marks the holder of a spin lock, it is switched out once it unlocks
+    thread::spawn(|| {
+        let hart = crate::cpu::id();
+        let lock = SpinMutex::new(());
+        let guard = lock.lock();
+        let before = thread::stat::get(hart).preemptions;
+        let sstatus = crate::trap::disable();
+        // well past the time slice of any scheduler
+        for _ in 0..1000 {
+            thread::tick();
+        }
+        crate::trap::restore(sstatus);
+        assert_eq!(
+            thread::stat::get(hart).preemptions,
+            before,
+            "preempted holding a spin lock"
+        );
+        drop(guard);
+        assert!(
+            thread::stat::get(hart).preemptions > before,
+            "preemption put off for good"
+        );
+        // with interrupts off as well, the switch waits until they are back on
+        let lock = IrqMutex::new(());
+        let guard = lock.lock();
+        let before = thread::stat::get(hart).preemptions;
+        for _ in 0..1000 {
+            thread::tick();
+        }
+        drop(guard);
+        assert!(
+            thread::stat::get(hart).preemptions > before,
+            "preemption put off for good by an irq lock"
+        );
+        println!("preemption test passed");
+        0
+    })
+    .expect("failed to start the preemption test")
+    .detach();
+
+    #[inline(never)]
+    fn worker(_num: usize) {
+        for _ in 0..100 {
+            let mut counter = COUNTER.lock();
+            let old = *counter;
+            // get preempted inside the critical section on purpose
+            thread::yield_now();
+            *counter = old + 1;
+        }
+        if BARRIER.wait() {
+            assert_eq!(*COUNTER.lock(), 400);
+            println!("sync test passed");
+        }
+        thread::exit(0);
+    }
+
+    for i in 0..4 {
+        thread::start(Thread::with_args().arg(i).create(worker as usize))
+            .expect("failed to start worker")
+            .detach();
+    }
+}
@@ -0,0 +1,80 @@
+use crate::sync::WaitQueue;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// a mutex that puts contending threads to sleep instead of spinning
+///
+/// it may be held across preemption, but must not be taken in interrupt context
+pub struct Mutex<T: ?Sized> {
+    locked: AtomicBool,
+    queue: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+pub struct MutexGuard<'a, T: ?Sized> {
+    pub(super) mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            queue: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// block until the lock is acquired
+    pub fn lock(&self) -> MutexGuard<T> {
+        self.queue.wait_until(|| self.acquire());
+        MutexGuard { mutex: self }
+    }
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        if self.acquire() {
+            Some(MutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+    fn acquire(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+    fn release(&self) {
+        self.locked.store(false, Ordering::Release);
+        self.queue.notify_one();
+    }
+}
+
+impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.release();
+    }
+}
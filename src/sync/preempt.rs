@@ -0,0 +1,58 @@
+//! preemption control
+//!
+//! the tick must not switch out a thread holding a spin lock,
+//! whoever comes next on this hart would spin for a lock that is not going away
+
+use crate::config::MAX_HARTS;
+use crate::cpu::PerCpu;
+use crate::trap;
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// preemption is off while it is not zero, interrupt handlers leave it as they found it
+static COUNT: PerCpu<Cell<usize>> = PerCpu::new([ZERO; MAX_HARTS]);
+const ZERO: Cell<usize> = Cell::new(0);
+
+/// preemption stays off on this hart until it is dropped, guards nest
+pub struct PreemptGuard {
+    // stuck to the hart it was taken on
+    _not_send: PhantomData<*mut ()>,
+}
+
+pub fn preempt_disable() -> PreemptGuard {
+    let count = COUNT.get();
+    count.set(count.get() + 1);
+    // keep the critical section from moving above the increment
+    compiler_fence(Ordering::SeqCst);
+    PreemptGuard {
+        _not_send: PhantomData,
+    }
+}
+
+impl Drop for PreemptGuard {
+    fn drop(&mut self) {
+        compiler_fence(Ordering::SeqCst);
+        preempt_enable();
+    }
+}
+
+/// the last one out makes up for a switch the tick had to put off
+fn preempt_enable() {
+    let count = COUNT.get();
+    count.set(count.get() - 1);
+    preempt_pending();
+}
+
+/// switch out now if the tick had to put it off and nothing holds it off anymore,
+/// with interrupts off it has to wait for whoever turns them back on
+pub(crate) fn preempt_pending() {
+    if preempt_count() == 0 && trap::enabled() {
+        crate::thread::preempt();
+    }
+}
+
+/// the number of guards alive on this hart
+pub(crate) fn preempt_count() -> usize {
+    COUNT.get().get()
+}
@@ -0,0 +1,44 @@
+use crate::sync::WaitQueue;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// a counting semaphore
+pub struct Semaphore {
+    count: AtomicUsize,
+    queue: WaitQueue,
+}
+
+impl Semaphore {
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: AtomicUsize::new(count),
+            queue: WaitQueue::new(),
+        }
+    }
+    /// take one unit, blocking until there is one
+    pub fn acquire(&self) {
+        self.queue.wait_until(|| self.try_acquire());
+    }
+    pub fn try_acquire(&self) -> bool {
+        let mut count = self.count.load(Ordering::Relaxed);
+        while count != 0 {
+            match self.count.compare_exchange_weak(
+                count,
+                count - 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => count = actual,
+            }
+        }
+        false
+    }
+    /// give one unit back, also fine from interrupt context
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        self.queue.notify_one();
+    }
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+}
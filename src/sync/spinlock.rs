@@ -0,0 +1,52 @@
+use crate::sync::{preempt_disable, PreemptGuard};
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+/// a spin lock that keeps its holder from being preempted
+///
+/// interrupts may still come in, data shared with their handlers wants an `IrqMutex`
+pub struct SpinMutex<T> {
+    inner: spin::Mutex<T>,
+}
+
+pub struct SpinGuard<'a, T> {
+    guard: ManuallyDrop<spin::MutexGuard<'a, T>>,
+    _preempt: PreemptGuard,
+}
+
+impl<T> SpinMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: spin::Mutex::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> SpinGuard<T> {
+        let preempt = preempt_disable();
+        SpinGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            _preempt: preempt,
+        }
+    }
+}
+
+impl<T> Deref for SpinGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for SpinGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for SpinGuard<'_, T> {
+    fn drop(&mut self) {
+        // unlock before preemption is back on, the guard goes right after
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+    }
+}
@@ -0,0 +1,64 @@
+use crate::sync::SpinMutex;
+use crate::thread::{self, TaskId};
+use crate::trap;
+use alloc::collections::VecDeque;
+
+/// threads blocked until someone notifies them
+///
+/// all operations run with interrupts disabled, so it can be notified from interrupt context
+pub struct WaitQueue {
+    /// in arrival order, created on first use as `VecDeque::new` is not const
+    waiters: SpinMutex<Option<VecDeque<TaskId>>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: SpinMutex::new(None),
+        }
+    }
+
+    /// block the current thread until notified
+    ///
+    /// wake ups may be spurious, callers are expected to check their condition again
+    pub fn wait(&self) {
+        thread::park(|tid| {
+            let mut waiters = self.waiters.lock();
+            waiters.get_or_insert_with(VecDeque::new).push_back(tid)
+        });
+    }
+
+    /// block the current thread until `ready` returns true
+    ///
+    /// `ready` is evaluated with interrupts disabled right before going to sleep,
+    /// so a notification in between cannot be lost
+    pub fn wait_until(&self, mut ready: impl FnMut() -> bool) {
+        let sstatus = trap::disable();
+        while !ready() {
+            self.wait();
+        }
+        trap::restore(sstatus);
+    }
+
+    /// wake up the longest waiting thread, false if there is none
+    pub fn notify_one(&self) -> bool {
+        let sstatus = trap::disable();
+        let tid = self.waiters.lock().as_mut().and_then(VecDeque::pop_front);
+        if let Some(tid) = tid {
+            thread::unpark(tid);
+        }
+        trap::restore(sstatus);
+        tid.is_some()
+    }
+
+    /// wake up all waiting threads and return how many there were
+    pub fn notify_all(&self) -> usize {
+        let sstatus = trap::disable();
+        let waiters = self.waiters.lock().take().unwrap_or_default();
+        for &tid in waiters.iter() {
+            thread::unpark(tid);
+        }
+        trap::restore(sstatus);
+        waiters.len()
+    }
+}
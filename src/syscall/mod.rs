@@ -0,0 +1,258 @@
+//! system calls from user mode, numbered as on Linux
+//!
+//! the number comes in a7, the arguments in a0-a5 and the result goes back in a0,
+//! negative for errors
+
+use crate::config::PAGE_SIZE;
+use crate::process::signal::{self, SigAction, SigError, SigSet};
+use crate::process::{self, ElfError, ExitStatus, LoadError, WaitError};
+use crate::trap::Frame;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::mem::size_of;
+
+const SYS_WRITE: usize = 64;
+const SYS_EXIT: usize = 93;
+const SYS_KILL: usize = 129;
+const SYS_RT_SIGACTION: usize = 134;
+const SYS_RT_SIGPROCMASK: usize = 135;
+const SYS_RT_SIGRETURN: usize = 139;
+const SYS_GETPID: usize = 172;
+const SYS_GETPPID: usize = 173;
+const SYS_CLONE: usize = 220;
+const SYS_EXECVE: usize = 221;
+const SYS_WAIT4: usize = 260;
+
+const ENOENT: isize = 2;
+const ESRCH: isize = 3;
+const EINTR: isize = 4;
+const E2BIG: isize = 7;
+const ENOEXEC: isize = 8;
+const EBADF: isize = 9;
+const ECHILD: isize = 10;
+const EAGAIN: isize = 11;
+const EFAULT: isize = 14;
+const EBUSY: isize = 16;
+const EINVAL: isize = 22;
+const ENOSYS: isize = 38;
+
+/// `wait4` returns at once if no child has exited
+const WNOHANG: usize = 1;
+/// longest string taken from user memory, terminating NUL included
+const MAX_STR: usize = 4096;
+/// most entries in argv or envp
+const MAX_ARGS: usize = 256;
+
+pub(crate) fn handle(tf: &mut Frame) {
+    // resume after the `ecall`, which has no compressed form
+    tf.sepc += 4;
+    let mut a = [0; 6];
+    a.copy_from_slice(&tf.x[10..16]);
+    let ret = match tf.x[17] {
+        SYS_WRITE => sys_write(a[0], a[1], a[2]),
+        SYS_EXIT => crate::thread::exit(a[0]),
+        SYS_KILL => sys_kill(a[0] as isize, a[1]),
+        SYS_RT_SIGACTION => sys_rt_sigaction(a[0], a[1], a[2], a[3]),
+        SYS_RT_SIGPROCMASK => sys_rt_sigprocmask(a[0], a[1], a[2], a[3]),
+        SYS_RT_SIGRETURN => signal::sigreturn(tf) as isize,
+        SYS_GETPID => process::current().map_or(0, |pid| pid as isize),
+        SYS_GETPPID => process::current()
+            .and_then(process::parent)
+            .map_or(0, |pid| pid as isize),
+        SYS_CLONE => sys_clone(a[1], tf),
+        SYS_EXECVE => sys_execve(a[0], a[1], a[2], tf),
+        SYS_WAIT4 => sys_wait4(a[0] as isize, a[1], a[2]),
+        n => {
+            println!("unknown system call {} at {:#x}", n, tf.sepc - 4);
+            -ENOSYS
+        }
+    };
+    tf.x[10] = ret as usize;
+}
+
+/// a copy of the NUL-terminated string at `ptr`
+fn user_str(ptr: usize) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut buf = [0; 256];
+    while bytes.len() < MAX_STR {
+        // never across a page boundary, the string may end right in front of an unmapped page
+        let addr = ptr.checked_add(bytes.len())?;
+        let len = min(buf.len(), PAGE_SIZE - addr % PAGE_SIZE);
+        process::copy_from_user(addr, &mut buf[..len]).ok()?;
+        match buf[..len].iter().position(|&b| b == 0) {
+            Some(nul) => {
+                bytes.extend_from_slice(&buf[..nul]);
+                return String::from_utf8(bytes).ok();
+            }
+            None => bytes.extend_from_slice(&buf[..len]),
+        }
+    }
+    None
+}
+
+/// copies of the strings in the NULL-terminated array at `ptr`, which may be NULL itself
+fn user_strs(ptr: usize) -> Option<Vec<String>> {
+    let mut strs = Vec::new();
+    if ptr == 0 {
+        return Some(strs);
+    }
+    for i in 0..MAX_ARGS {
+        let mut entry = [0; 8];
+        process::copy_from_user(ptr.checked_add(i * 8)?, &mut entry).ok()?;
+        match usize::from_le_bytes(entry) {
+            0 => return Some(strs),
+            s => strs.push(user_str(s)?),
+        }
+    }
+    None
+}
+
+/// only stdout and stderr, both go to the console
+fn sys_write(fd: usize, buf: usize, len: usize) -> isize {
+    if fd != 1 && fd != 2 {
+        return -EBADF;
+    }
+    // a piece at a time, the address space is locked while copying
+    let mut chunk = [0; 256];
+    let mut done = 0;
+    while done < len {
+        let piece = min(chunk.len(), len - done);
+        if process::copy_from_user(buf.wrapping_add(done), &mut chunk[..piece]).is_err() {
+            return -EFAULT;
+        }
+        for &b in chunk[..piece].iter() {
+            crate::io::putchar(b as char);
+        }
+        done += piece;
+    }
+    len as isize
+}
+
+/// only `fork`, sharing the address space as a thread is not supported
+fn sys_clone(stack: usize, tf: &Frame) -> isize {
+    if stack != 0 {
+        return -EINVAL;
+    }
+    match process::fork(tf) {
+        Ok(pid) => pid as isize,
+        Err(_) => -EAGAIN,
+    }
+}
+
+/// the path names one of the programs built into the kernel
+fn sys_execve(path: usize, argv: usize, envp: usize, tf: &mut Frame) -> isize {
+    let (path, argv, envp) = match (user_str(path), user_strs(argv), user_strs(envp)) {
+        (Some(path), Some(argv), Some(envp)) => (path, argv, envp),
+        _ => return -EFAULT,
+    };
+    let elf = match process::program(&path) {
+        Some(elf) => elf,
+        None => return -ENOENT,
+    };
+    // the strings live in the address space about to go, they have been copied
+    let argv: Vec<&str> = argv.iter().map(|s| s.as_str()).collect();
+    let envp: Vec<&str> = envp.iter().map(|s| s.as_str()).collect();
+    match process::exec(&path, elf, &argv, &envp, tf) {
+        Ok(()) => 0,
+        Err(LoadError::Elf(ElfError::ArgsTooLong)) => -E2BIG,
+        Err(LoadError::Elf(_)) => -ENOEXEC,
+        Err(LoadError::Busy) => -EBUSY,
+        Err(LoadError::Spawn(_)) => -EAGAIN,
+    }
+}
+
+/// the exit code ends up in bits 8-15 of the status, as for a normal exit on Linux
+fn sys_wait4(pid: isize, wstatus: usize, options: usize) -> isize {
+    let pid = match pid {
+        -1 => None,
+        pid if pid > 0 => Some(pid as usize),
+        _ => return -EINVAL,
+    };
+    // the status of a reaped child would be lost, make sure it can be stored first
+    if wstatus != 0 && process::write_user(wstatus, &0i32).is_err() {
+        return -EFAULT;
+    }
+    match process::wait(pid, options & WNOHANG == 0) {
+        Ok(Some((pid, exit))) => {
+            if wstatus != 0 && process::write_user(wstatus, &status(exit)).is_err() {
+                return -EFAULT;
+            }
+            pid as isize
+        }
+        Ok(None) => 0,
+        Err(WaitError::NoChildren) => -ECHILD,
+        Err(WaitError::Interrupted) => -EINTR,
+    }
+}
+
+/// a terminating signal goes in the low 7 bits instead
+fn status(exit: ExitStatus) -> i32 {
+    match exit {
+        ExitStatus::Exited(code) => ((code & 0xff) << 8) as i32,
+        ExitStatus::Signaled(sig) => (sig & 0x7f) as i32,
+    }
+}
+
+/// only to a single process, there are no process groups
+fn sys_kill(pid: isize, sig: usize) -> isize {
+    if pid <= 0 {
+        return -EINVAL;
+    }
+    match signal::kill(pid as usize, sig) {
+        Ok(()) => 0,
+        Err(err) => sig_errno(err),
+    }
+}
+
+fn sys_rt_sigaction(sig: usize, act: usize, oldact: usize, sigsetsize: usize) -> isize {
+    if sigsetsize != size_of::<SigSet>() {
+        return -EINVAL;
+    }
+    let new = match act {
+        0 => None,
+        // plain integers, any bits make a valid one
+        act => match unsafe { process::read_user::<SigAction>(act) } {
+            Ok(new) => Some(new),
+            Err(_) => return -EFAULT,
+        },
+    };
+    match signal::action(sig, new) {
+        Ok(old) => {
+            if oldact != 0 && process::write_user(oldact, &old).is_err() {
+                return -EFAULT;
+            }
+            0
+        }
+        Err(err) => sig_errno(err),
+    }
+}
+
+fn sys_rt_sigprocmask(how: usize, set: usize, oldset: usize, sigsetsize: usize) -> isize {
+    if sigsetsize != size_of::<SigSet>() {
+        return -EINVAL;
+    }
+    let new = match set {
+        0 => None,
+        set => match unsafe { process::read_user::<SigSet>(set) } {
+            Ok(new) => Some(new),
+            Err(_) => return -EFAULT,
+        },
+    };
+    match signal::procmask(how, new) {
+        Ok(old) => {
+            if oldset != 0 && process::write_user(oldset, &old).is_err() {
+                return -EFAULT;
+            }
+            0
+        }
+        Err(err) => sig_errno(err),
+    }
+}
+
+fn sig_errno(err: SigError) -> isize {
+    match err {
+        SigError::Invalid => -EINVAL,
+        SigError::NoSuchProcess => -ESRCH,
+    }
+}
@@ -0,0 +1,67 @@
+//! how thread creation and switching scale with the number of threads,
+//! run with `bench` on the kernel command line
+
+use crate::thread::{self, proc, ExitCode, JoinHandle, Thread};
+use crate::time::Instant;
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+
+/// live threads in each round, all of them in the run queue while they yield
+const THREADS: [usize; 4] = [16, 128, 512, 1024];
+const YIELDS: usize = 16;
+/// total threads created and joined in batches, to check that ids are recycled in O(1)
+const CHURN: usize = 4096;
+const BATCH: usize = 16;
+/// the threads do nothing but yield, with full kernel stacks a thousand would not fit in the heap
+const STACK_SIZE: usize = 0x2000;
+
+pub(crate) fn run() {
+    println!("+++ thread benchmark +++");
+    // the log would be all we measure
+    proc::TRACE.store(false, Ordering::Relaxed);
+    for &n in THREADS.iter() {
+        let begin = Instant::now();
+        let handles: Vec<_> = (0..n)
+            .map(|_| {
+                spawn(|| {
+                    for _ in 0..YIELDS {
+                        thread::yield_now();
+                    }
+                    0
+                })
+            })
+            .collect();
+        let spawned = begin.elapsed();
+        for handle in handles {
+            handle.join();
+        }
+        let switched = begin.elapsed() - spawned;
+        println!(
+            "{:>5} threads: {:?} per spawn, {:?} per yield",
+            n,
+            spawned / n as u32,
+            switched / (n * YIELDS) as u32
+        );
+    }
+    let begin = Instant::now();
+    for _ in 0..CHURN / BATCH {
+        let handles: Vec<_> = (0..BATCH).map(|_| spawn(|| 0)).collect();
+        for handle in handles {
+            handle.join();
+        }
+    }
+    println!(
+        "{} threads created and joined: {:?} each",
+        CHURN,
+        begin.elapsed() / CHURN as u32
+    );
+    proc::TRACE.store(true, Ordering::Relaxed);
+}
+
+fn spawn(f: fn() -> ExitCode) -> JoinHandle {
+    Thread::with_args()
+        .name("bench")
+        .stack_size(STACK_SIZE)
+        .spawn(f)
+        .expect("out of threads")
+}
@@ -1,6 +1,10 @@
 use crate::trap;
 use bit_field::BitField;
 
+extern "C" {
+    fn __trapret();
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub(crate) struct Context {
@@ -8,6 +12,11 @@ pub(crate) struct Context {
     ///
     /// addr points to the beginning of context content on stack
     pub(crate) addr: usize,
+    /// the address space to run in, only `exec` changes it
+    ///
+    /// 0 for kernel threads, which run in whichever one they find loaded,
+    /// as the kernel is mapped the same everywhere
+    pub(crate) satp: usize,
 }
 impl Context {
     #[naked]
@@ -16,22 +25,51 @@ impl Context {
         asm!(include_str!("switch.asm"):::: "volatile")
     }
     /// create content of the new kernel context and push it onto stack
-    pub(crate) unsafe fn new_kthread(sepc: usize, sp: usize, satp: usize) -> Self {
-        let content = ContextContent::new_kthread(sepc, sp, satp);
+    pub(crate) unsafe fn new_kthread(sepc: usize, sp: usize, satp: usize, tp: usize) -> Self {
+        let content = ContextContent::new_kthread(sepc, sp, tp);
         // allocate space on stack
         let ptr = (sp as *mut ContextContent).sub(1);
         // put content there
         *ptr = content;
         // cast to a Context wrapper
-        Self { addr: ptr as usize }
+        Self {
+            addr: ptr as usize,
+            satp,
+        }
+    }
+    /// create content of a new user context returning to `tf` and push it onto its kernel stack
+    ///
+    /// `tp` is the kernel's, traps from user mode find it above the trap frame,
+    /// so room is left for it at the top of the kernel stack, see `trap.asm`
+    pub(crate) unsafe fn new_uthread(
+        tf: trap::Frame,
+        kstack_top: usize,
+        satp: usize,
+        tp: usize,
+    ) -> Self {
+        let content = ContextContent {
+            ra: __trapret as usize,
+            s: [0; 12],
+            tp,
+            tf,
+        };
+        // keep sp 16 bytes aligned
+        let top = kstack_top - 2 * core::mem::size_of::<usize>();
+        let ptr = (top as *mut ContextContent).sub(1);
+        *ptr = content;
+        Self {
+            addr: ptr as usize,
+            satp,
+        }
     }
 }
 
 #[repr(C)]
 pub(crate) struct ContextContent {
     pub(crate) ra: usize,
-    satp: usize,
     s: [usize; 12],
+    /// thread pointer, see `tls`
+    tp: usize,
     pub(crate) tf: trap::Frame,
 }
 
@@ -41,13 +79,10 @@ impl ContextContent {
     /// what we need:
     /// program => sepc
     /// stack => sp
-    /// page table => satp
-    fn new_kthread(sepc: usize, sp: usize, satp: usize) -> Self {
+    /// thread-local storage => tp
+    fn new_kthread(sepc: usize, sp: usize, tp: usize) -> Self {
         // although we use `__trapret` to set up the stack and registers
         // it doesn't mean that it has anything to do with interrupt
-        extern "C" {
-            fn __trapret();
-        }
         let ra = __trapret as usize;
         let s = [0usize; 12];
         let mut sstatus: usize;
@@ -64,8 +99,33 @@ impl ContextContent {
         // GOSH!!! That's a relief
         let mut tf: trap::Frame = unsafe { core::mem::zeroed() };
         tf.sstatus = sstatus;
+        // an entry that returns lands in `exit` with its return value
+        tf.x[1] = crate::thread::exit_on_return as usize;
         tf.x[2] = sp;
+        // `__trapret` restores tp from here as well
+        tf.x[4] = tp;
         tf.sepc = sepc;
-        Self { ra, satp, s, tf }
+        Self { ra, s, tp, tf }
+    }
+}
+
+/// the registers to enter user mode with, at `sepc` with the user stack at `usp`
+///
+/// the user starts without thread-local storage
+pub(crate) fn user_frame(sepc: usize, usp: usize) -> trap::Frame {
+    let mut sstatus: usize;
+    unsafe {
+        asm!("csrr $0, sstatus": "=r"(sstatus):::"volatile");
     }
+    // SPP (previous privilege) bit as user, `sret` lands in user mode
+    sstatus.set_bit(8, false);
+    // SPIE (previous interrupt enable)
+    sstatus.set_bit(5, true);
+    // SIE bit
+    sstatus.set_bit(1, false);
+    let mut tf: trap::Frame = unsafe { core::mem::zeroed() };
+    tf.sstatus = sstatus;
+    tf.x[2] = usp;
+    tf.sepc = sepc;
+    tf
 }
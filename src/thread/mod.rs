@@ -1,18 +1,34 @@
 use crate::config::*;
+use crate::process::Pid;
 use crate::thread::sched::ThreadPool;
+use crate::time::{Duration, Instant};
 use crate::trap;
 use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::cmp::min;
 
+mod bench;
 mod context;
 mod proc;
 mod sched;
+pub mod stat;
+mod tls;
+
+pub(crate) use context::user_frame;
+pub(crate) use sched::{AdmissionError, DeadlineParams, ExitCode, SpawnError, TaskId, ThreadMeta};
+pub use stat::ThreadStat;
 
 #[repr(C)]
 #[derive(Debug)]
 pub(crate) struct Thread {
     context: context::Context,
     kstack: KStack,
+    tls: tls::Tls,
+    name: String,
+    created: Instant,
+    /// None for kernel threads
+    pid: Option<Pid>,
 }
 impl Thread {
     fn switch(&mut self, target: &mut Self) {
@@ -24,15 +40,43 @@ impl Thread {
     fn new(entry: usize) -> Box<Self> {
         Thread::with_args().create(entry)
     }
-    fn with_args() -> ThreadArgs {
-        ThreadArgs { n: 0, args: [0; 8] }
+    pub(crate) fn with_args() -> ThreadArgs {
+        ThreadArgs {
+            n: 0,
+            args: [0; 8],
+            name: String::new(),
+            pid: None,
+            satp: 0,
+            stack_size: KERNEL_STACK_SIZE,
+        }
     }
     fn boot_thread() -> Box<Self> {
         // no need to worry about ra
         // because before the switch from boot thread
         // ra has automatically been set to next instruction following switch
-        // DANGER!!! zero inited a pointer
-        Box::new(unsafe { core::mem::zeroed() })
+        Box::new(Thread {
+            context: context::Context { addr: 0, satp: 0 },
+            kstack: KStack { bottom: 0, size: 0 },
+            // it was never set up for the boot thread
+            tls: tls::Tls::empty(),
+            name: String::from("boot"),
+            created: Instant::now(),
+            pid: None,
+        })
+    }
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+    pub(crate) fn created(&self) -> Instant {
+        self.created
+    }
+    /// the process it belongs to, None for kernel threads
+    pub(crate) fn pid(&self) -> Option<Pid> {
+        self.pid
+    }
+    /// where the kernel stack is, to measure it with `StackBounds::used`
+    pub(crate) fn stack_bounds(&self) -> StackBounds {
+        self.kstack.bounds()
     }
     fn set_args(&mut self, args: &[usize]) {
         let context = &mut self.context;
@@ -42,44 +86,198 @@ impl Thread {
     }
 }
 
-struct ThreadArgs {
+pub(crate) struct ThreadArgs {
     n: usize,
     args: [usize; 8],
+    name: String,
+    pid: Option<Pid>,
+    satp: usize,
+    stack_size: usize,
 }
 impl ThreadArgs {
-    fn arg(mut self, arg: usize) -> Self {
+    pub(crate) fn arg(mut self, arg: usize) -> Self {
         self.args[self.n] = arg;
         self.n += 1;
         self
     }
-    fn create(self, entry: usize) -> Box<Thread> {
-        let kstack = KStack::new();
-        let context = unsafe {
-            context::Context::new_kthread(entry, kstack.top(), riscv::register::satp::read().bits())
-        };
+    /// named after its entry point by default
+    pub(crate) fn name(mut self, name: &str) -> Self {
+        self.name = String::from(name);
+        self
+    }
+    /// run in the address space `satp` of process `pid` rather than as a kernel thread
+    pub(crate) fn process(mut self, pid: Pid, satp: usize) -> Self {
+        self.pid = Some(pid);
+        self.satp = satp;
+        self
+    }
+    /// a kernel stack of `size` bytes, a power of two, for threads known to stay shallow
+    ///
+    /// nothing catches an overflow, there is no guard page below the stack
+    pub(crate) fn stack_size(mut self, size: usize) -> Self {
+        assert!(
+            size.is_power_of_two() && size >= PAGE_SIZE,
+            "kernel stack size {:#x} not a power of two of at least a page",
+            size
+        );
+        self.stack_size = size;
+        self
+    }
+    pub(crate) fn create(self, entry: usize) -> Box<Thread> {
+        let kstack = KStack::new(self.stack_size);
+        let tls = tls::Tls::new();
+        let context =
+            unsafe { context::Context::new_kthread(entry, kstack.top(), self.satp, tls.tp()) };
         let content = unsafe { &mut *(context.addr as *mut context::ContextContent) };
         content.tf.x[10..18].clone_from_slice(&self.args[..]);
-        Box::new(Thread { context, kstack })
+        self.finish(entry, context, kstack, tls)
+    }
+    /// a thread entering user mode at `entry` with its user stack at `sp`
+    pub(crate) fn create_user(self, entry: usize, sp: usize) -> Box<Thread> {
+        self.resume_user(context::user_frame(entry, sp))
+    }
+    /// a thread returning to user mode with the registers in `tf`, the args are ignored
+    pub(crate) fn resume_user(self, tf: trap::Frame) -> Box<Thread> {
+        assert!(self.pid.is_some(), "user threads belong to a process");
+        let kstack = KStack::new(self.stack_size);
+        let tls = tls::Tls::new();
+        let entry = tf.sepc;
+        let context =
+            unsafe { context::Context::new_uthread(tf, kstack.top(), self.satp, tls.tp()) };
+        self.finish(entry, context, kstack, tls)
+    }
+    fn finish(
+        self,
+        entry: usize,
+        context: context::Context,
+        kstack: KStack,
+        tls: tls::Tls,
+    ) -> Box<Thread> {
+        let name = if self.name.is_empty() {
+            alloc::format!("{:#x}", entry)
+        } else {
+            self.name
+        };
+        Box::new(Thread {
+            context,
+            kstack,
+            tls,
+            name,
+            created: Instant::now(),
+            pid: self.pid,
+        })
+    }
+    /// start a thread running `f`, it exits with whatever `f` returns
+    ///
+    /// the args are ignored, `f` captures whatever it needs
+    pub(crate) fn spawn<F>(mut self, f: F) -> Result<JoinHandle, SpawnError>
+    where
+        F: FnOnce() -> ExitCode + Send + 'static,
+    {
+        type Main = Box<dyn FnOnce() -> ExitCode + Send>;
+        extern "C" fn trampoline(main: usize) -> ! {
+            let main = unsafe { Box::from_raw(main as *mut Main) };
+            exit(main())
+        }
+        if self.name.is_empty() {
+            self.name = String::from(core::any::type_name::<F>());
+        }
+        // a thin pointer to the fat one fits in a register
+        let main = Box::into_raw(Box::new(Box::new(f) as Main));
+        self.n = 0;
+        start(self.arg(main as usize).create(trampoline as usize)).map_err(|err| {
+            // it is never going to run
+            drop(unsafe { Box::from_raw(main) });
+            err
+        })
+    }
+}
+
+/// owned permission to join a thread, dropping it detaches the thread
+pub(crate) struct JoinHandle {
+    tid: TaskId,
+}
+impl JoinHandle {
+    pub(crate) fn tid(&self) -> TaskId {
+        self.tid
+    }
+    /// block until the thread exits and get its exit code
+    pub(crate) fn join(self) -> ExitCode {
+        let code = proc::CPU.get().join(self.tid);
+        core::mem::forget(self);
+        code
+    }
+    /// let the thread be freed as soon as it exits
+    pub(crate) fn detach(self) {
+        drop(self)
+    }
+}
+impl Drop for JoinHandle {
+    fn drop(&mut self) {
+        proc::CPU.get().detach(self.tid)
     }
 }
 
 #[derive(Debug)]
 pub(crate) struct KStack {
     bottom: usize,
+    size: usize,
 }
+/// fills a fresh kernel stack, whatever is still there has never been used
+const STACK_PAINT: usize = usize::max_value() / 0xff * 0xa5;
+/// this much of the stack left untouched is taken for its end
+const STACK_GAP: usize = 1024;
+
 impl KStack {
-    fn new() -> Self {
+    fn new(size: usize) -> Self {
         let bottom = unsafe {
             // be careful with hand-written RAII
             alloc::alloc::alloc(
-                alloc::alloc::Layout::from_size_align(KERNEL_STACK_SIZE, KERNEL_STACK_SIZE)
+                alloc::alloc::Layout::from_size_align(size, size)
                     .expect("kernel stack layout illegal"),
             ) as usize
         };
-        Self { bottom }
+        assert!(bottom != 0, "out of memory for a kernel stack");
+        unsafe { core::ptr::write_bytes(bottom as *mut u8, STACK_PAINT as u8, size) };
+        Self { bottom, size }
     }
     fn top(&self) -> usize {
-        self.bottom + KERNEL_STACK_SIZE
+        self.bottom + self.size
+    }
+    fn bounds(&self) -> StackBounds {
+        StackBounds {
+            bottom: self.bottom,
+            top: self.top(),
+        }
+    }
+}
+
+/// a kernel stack as seen from outside, which can be measured without holding its thread
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct StackBounds {
+    bottom: usize,
+    top: usize,
+}
+impl StackBounds {
+    /// high-water mark in bytes, the stack grows down from the top
+    ///
+    /// reads a word at a time from the top down to the first `STACK_GAP` bytes still painted,
+    /// a frame leaving that much untouched hides whatever is below it.
+    /// if the thread has gone meanwhile, this reads what the heap has put in its place
+    pub(crate) fn used(self) -> usize {
+        // the boot stack is not ours
+        if self.bottom == 0 {
+            return 0;
+        }
+        let mut lowest = self.top;
+        let mut addr = self.top;
+        while addr > self.bottom && lowest - addr < STACK_GAP {
+            addr -= core::mem::size_of::<usize>();
+            if unsafe { core::ptr::read_volatile(addr as *const usize) } != STACK_PAINT {
+                lowest = addr;
+            }
+        }
+        self.top - lowest
     }
 }
 impl Drop for KStack {
@@ -90,7 +288,7 @@ impl Drop for KStack {
             unsafe {
                 alloc::alloc::dealloc(
                     self.bottom as _,
-                    alloc::alloc::Layout::from_size_align(KERNEL_STACK_SIZE, KERNEL_STACK_SIZE)
+                    alloc::alloc::Layout::from_size_align(self.size, self.size)
                         .expect("kernel stack layout illegal"),
                 )
             }
@@ -102,28 +300,82 @@ pub(crate) fn init() {
     println!("+++ setting up thread +++");
     test();
     unsafe {
-        let pool = Box::new(sched::ThreadPool::new(
-            1024,
-            Box::new(sched::RRScheduler::new()),
-        ));
+        // `sched=<name>` on the command line overrides the cargo feature
+        let mut name = crate::cmdline::option("sched").unwrap_or(sched::default_name());
+        let scheduler = sched::by_name(name).unwrap_or_else(|| {
+            println!("unknown scheduler {:?}", name);
+            name = sched::default_name();
+            sched::by_name(name).unwrap()
+        });
+        println!("using the {} scheduler", name);
+        let pool = Box::new(sched::ThreadPool::new(MAX_THREADS, scheduler));
         let idle = Thread::with_args()
-            .arg(&proc::CPU as *const _ as usize)
+            .arg(proc::CPU.get() as *const _ as usize)
             .create(proc::Processor::idle as usize);
-        proc::CPU.init(pool, idle);
+        proc::CPU.get().init(pool, idle);
+
+        // every thread starts with its own copy from `.tdata`
+        #[thread_local]
+        static mut WHO: usize = usize::max_value();
 
         #[inline(never)]
-        fn hello(num: usize) {
+        fn hello(num: usize) -> ExitCode {
+            unsafe {
+                assert_eq!(WHO, usize::max_value());
+                WHO = num;
+            }
             println!("[{:04x}] hello, world!", num);
             for i in 0..0xff {
                 print!("{}", i);
+                if i % 0x40 == 0 {
+                    yield_now();
+                }
             }
-            println!("\n[{:04x}] hello, world!", num);
-            exit(num);
+            let begin = Instant::now();
+            sleep(Duration::from_millis(10 * num as u64));
+            assert_eq!(
+                unsafe { WHO },
+                num,
+                "thread-local overwritten by another thread"
+            );
+            println!(
+                "\n[{:04x}] hello, world! after {:?} asleep",
+                num,
+                begin.elapsed()
+            );
+            num
         }
 
-        for i in 0..8 {
-            proc::CPU.push(Thread::with_args().arg(i).create(hello as usize));
-        }
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                Thread::with_args()
+                    .name(&alloc::format!("hello-{}", i))
+                    .spawn(move || hello(i))
+                    .expect("failed to start hello")
+            })
+            .collect();
+        Thread::with_args()
+            .name("reaper")
+            .spawn(move || {
+                dump();
+                for handle in handles.into_iter() {
+                    let tid = handle.tid();
+                    println!("thread {} joined with code {}", tid, handle.join());
+                }
+                0
+            })
+            .expect("failed to start the reaper")
+            .detach();
+    }
+    crate::sync::test();
+    if crate::cmdline::option("bench").is_some() {
+        Thread::with_args()
+            .name("bench")
+            .spawn(|| {
+                bench::run();
+                0
+            })
+            .expect("failed to start the benchmark");
     }
 }
 
@@ -169,13 +421,132 @@ fn test() {
 }
 
 pub(crate) fn tick() {
-    unsafe { proc::CPU.tick() }
+    proc::CPU.get().tick()
+}
+
+/// switch out the current thread if the tick had to put that off, now that preemption is back on
+pub(crate) fn preempt() {
+    proc::CPU.get().preempt()
+}
+
+/// make a thread runnable, fails when the thread table is full
+pub(crate) fn start(thread: Box<Thread>) -> Result<JoinHandle, SpawnError> {
+    let tid = proc::CPU.get().push(thread)?;
+    Ok(JoinHandle { tid })
+}
+
+/// start a thread running `f`, it exits with whatever `f` returns
+pub(crate) fn spawn<F>(f: F) -> Result<JoinHandle, SpawnError>
+where
+    F: FnOnce() -> ExitCode + Send + 'static,
+{
+    Thread::with_args().spawn(f)
+}
+
+/// the thread running on this hart, None if it is the idle or boot thread
+pub(crate) fn current() -> Option<TaskId> {
+    proc::CPU.get().current()
+}
+
+/// the process of the thread running on this hart, None for kernel threads
+pub(crate) fn current_pid() -> Option<Pid> {
+    proc::CPU.get().current_pid()
+}
+
+/// give up the CPU and let others run, the current thread stays runnable
+pub(crate) fn yield_now() {
+    proc::CPU.get().yield_now()
+}
+
+/// larger means a bigger share of the CPU, or precedence, depending on the scheduler
+pub(crate) fn set_priority(tid: TaskId, prio: usize) {
+    proc::CPU.get().set_priority(tid, prio)
+}
+
+/// -20 (greedy) to 19 (nice), weights the CPU share under the fair scheduler
+pub(crate) fn set_nice(tid: TaskId, nice: isize) {
+    proc::CPU.get().set_nice(tid, nice)
+}
+
+/// move a thread into the earliest deadline first real-time class, or back out with None
+///
+/// fails if the scheduler has no such class or cannot guarantee the parameters
+pub(crate) fn set_deadline(
+    tid: TaskId,
+    params: Option<DeadlineParams>,
+) -> Result<(), AdmissionError> {
+    proc::CPU.get().set_deadline(tid, params)
+}
+
+/// what is there on this hart, like `ps`
+pub(crate) fn ps() -> Vec<ThreadMeta> {
+    proc::CPU.get().ps()
+}
+
+/// print the output of `ps` as a table
+pub(crate) fn dump() {
+    println!(
+        "{:>5} {:>5} {:>5} {:<12} {:>14} {:>8} NAME",
+        "TID", "PPID", "PID", "STATUS", "CPU", "STACK"
+    );
+    for t in ps() {
+        let parent = t
+            .parent
+            .map_or(String::from("-"), |p| alloc::format!("{}", p));
+        let pid = t.pid.map_or(String::from("-"), |p| alloc::format!("{}", p));
+        let status = alloc::format!("{:?}", t.status);
+        let cpu = alloc::format!("{:?}", t.cpu);
+        println!(
+            "{:>5} {:>5} {:>5} {:<12} {:>14} {:>8} {}",
+            t.tid, parent, pid, status, cpu, t.stack, t.name
+        );
+    }
+}
+
+/// scheduler statistics of a thread, None if there is no such thread
+pub(crate) fn stat(tid: TaskId) -> Option<ThreadStat> {
+    proc::CPU.get().stat(tid)
+}
+
+/// block the current thread, `enqueue` gets its id to arrange for an `unpark` later
+///
+/// interrupts stay disabled from `enqueue` until the thread is off the CPU,
+/// so the wake up cannot be lost
+pub(crate) fn park(enqueue: impl FnOnce(TaskId)) {
+    proc::CPU.get().park(enqueue)
+}
+
+/// make a parked thread runnable again, also fine from interrupt context
+pub(crate) fn unpark(tid: TaskId) {
+    proc::CPU.get().wake(tid)
+}
+
+/// block the current thread for at least `duration`
+pub(crate) fn sleep(duration: Duration) {
+    sleep_until(Instant::now() + duration)
+}
+
+/// block the current thread until `deadline` has passed
+pub(crate) fn sleep_until(deadline: Instant) {
+    proc::CPU.get().sleep_until(deadline)
 }
 
 pub(crate) fn run() {
-    unsafe { proc::CPU.run() }
+    proc::CPU.get().run()
+}
+
+/// move the running thread into the address space `satp`, for `exec`
+///
+/// the caller has to make sure the old one is not needed anymore
+pub(crate) fn set_satp(satp: usize) {
+    proc::CPU.get().set_satp(satp)
+}
+
+pub(crate) fn exit(code: usize) -> ! {
+    proc::CPU.get().exit(code)
 }
 
-fn exit(code: usize) -> ! {
-    unsafe { proc::CPU.exit(code) }
+/// where a thread entry returns to, with its return value still in a0
+pub(crate) extern "C" fn exit_on_return(code: usize) -> ! {
+    exit(code)
 }
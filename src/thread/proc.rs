@@ -1,94 +1,303 @@
-use crate::thread::sched::{TaskId, ThreadPool};
-use crate::thread::Thread;
+use crate::config::MAX_HARTS;
+use crate::cpu::PerCpu;
+use crate::process::{self, Pid};
+use crate::sync::{preempt_count, IrqMutex};
+use crate::thread::sched::{
+    AdmissionError, DeadlineParams, ExitCode, SpawnError, TaskId, ThreadMeta, ThreadPool,
+};
+use crate::thread::{Thread, ThreadStat};
+use crate::time::Instant;
 use crate::trap;
+use crate::trap::timer;
 use alloc::boxed::Box;
-use core::borrow::BorrowMut;
-use spin::Mutex;
+use alloc::vec::Vec;
+use core::cell::{Cell, UnsafeCell};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Once;
 
-pub(crate) static mut CPU: Processor = Processor::new();
+// nothing to run until `init`
+const OFFLINE: Processor = Processor::new();
+pub(crate) static CPU: PerCpu<Processor> = PerCpu::new([OFFLINE; MAX_HARTS]);
+
+/// log every switch to and from the idle thread, and every exit
+pub(crate) static TRACE: AtomicBool = AtomicBool::new(true);
 
 struct ProcessorInner {
-    pool: Box<ThreadPool>,
-    idle: Box<Thread>,
-    cur: Option<(TaskId, Box<Thread>)>,
+    /// also reached from the timer interrupt
+    pool: IrqMutex<Box<ThreadPool>>,
+    /// the idle and the current thread are only reached through `threads`
+    idle: UnsafeCell<Box<Thread>>,
+    cur: UnsafeCell<Option<(TaskId, Box<Thread>)>>,
+    /// who `cur` is, for the running thread to ask without touching `cur`
+    tid: Cell<Option<TaskId>>,
+    pid: Cell<Option<Pid>>,
+    /// the current thread is leaving because its time slice is used up
+    preempted: Cell<bool>,
+    /// its time slice ran out while preemption was off, it leaves as soon as it is back on
+    resched: Cell<bool>,
+}
+impl ProcessorInner {
+    /// the idle thread and the current one, to switch between them or set up the switch
+    ///
+    /// unsafe: interrupts must be off so that nothing else on this hart gets here meanwhile,
+    /// and the references must be gone by the time they are turned back on
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn threads(&self) -> (&mut Box<Thread>, &mut Option<(TaskId, Box<Thread>)>) {
+        debug_assert!(!trap::enabled(), "switching threads with interrupts on");
+        (&mut *self.idle.get(), &mut *self.cur.get())
+    }
 }
 pub(crate) struct Processor {
-    inner: Option<ProcessorInner>,
+    inner: Once<ProcessorInner>,
 }
 impl Processor {
     const fn new() -> Self {
-        Self { inner: None }
+        Self { inner: Once::new() }
+    }
+    pub(crate) fn init(&self, pool: Box<ThreadPool>, idle: Box<Thread>) {
+        self.inner.call_once(|| ProcessorInner {
+            pool: IrqMutex::new(pool),
+            idle: UnsafeCell::new(idle),
+            cur: UnsafeCell::new(None),
+            tid: Cell::new(None),
+            pid: Cell::new(None),
+            preempted: Cell::new(false),
+            resched: Cell::new(false),
+        });
+    }
+    pub(crate) fn push(&self, thread: Box<Thread>) -> Result<TaskId, SpawnError> {
+        let parent = self.current();
+        let tid = self.inner().pool.lock().push(thread, parent)?;
+        // someone may need to be preempted for it
+        timer::start_tick();
+        Ok(tid)
     }
-    pub(crate) fn init(&mut self, pool: Box<ThreadPool>, idle: Box<Thread>) {
-        *self.inner.borrow_mut() = Some(ProcessorInner {
-            pool,
-            idle,
-            cur: None,
-        })
+    fn inner(&self) -> &ProcessorInner {
+        self.inner.r#try().expect("processor not initialized")
     }
-    pub(crate) fn push(&mut self, thread: Box<Thread>) {
-        self.inner().pool.push(thread);
+    pub(crate) fn current(&self) -> Option<TaskId> {
+        self.inner.r#try().and_then(|inner| inner.tid.get())
     }
-    fn inner(&mut self) -> &mut ProcessorInner {
-        self.inner
-            .borrow_mut()
-            .as_mut()
-            .expect("processor not initialized")
+    pub(crate) fn current_pid(&self) -> Option<Pid> {
+        self.inner.r#try().and_then(|inner| inner.pid.get())
     }
     #[inline(never)]
-    pub(crate) fn idle(&mut self) -> ! {
+    pub(crate) fn idle(&self) -> ! {
         loop {
             // came back to life, turn off interrupt
             trap::disable();
+            trap::watchdog::touch();
             let inner = self.inner();
-            if let Some(t) = inner.pool.pick() {
-                inner.cur = Some(t);
-                let cur = inner
-                    .cur
+            let mut pool = inner.pool.lock();
+            let picked = pool.pick();
+            let needs_tick = pool.needs_tick();
+            // with nobody else to switch to, the tick would only bring us back here for nothing
+            let alone = pool.ready() == 0 && !needs_tick;
+            drop(pool);
+            if let Some(t) = picked {
+                if alone {
+                    timer::stop_tick();
+                } else {
+                    timer::start_tick();
+                }
+                let trace = TRACE.load(Ordering::Relaxed);
+                if trace {
+                    println!(">>> switching to thread {} ({})", t.0, t.1.name());
+                }
+                inner.tid.set(Some(t.0));
+                inner.pid.set(t.1.pid());
+                let (idle, cur) = unsafe { inner.threads() };
+                *cur = Some(t);
+                let cur = cur
                     .as_mut()
                     .expect("I just put it in there, this should not happen!");
-                println!(">>> switching to thread {}", cur.0);
-                inner.idle.switch(&mut cur.1);
-                println!("<<< switched back to idle thread");
-                let (tid, thread) = self
-                    .inner()
-                    .cur
+                idle.switch(&mut cur.1);
+                if trace {
+                    println!("<<< switched back to idle thread");
+                }
+                let (_, cur) = unsafe { inner.threads() };
+                let (tid, thread) = cur
                     .take()
                     .expect("I just put it in there, this should not happen!");
-                self.inner().pool.r#yield(tid, thread);
+                inner.tid.set(None);
+                inner.pid.set(None);
+                let preempted = inner.preempted.replace(false);
+                // not a debt of whoever runs next
+                inner.resched.set(false);
+                inner.pool.lock().r#yield(tid, thread, preempted);
             } else {
                 // println!("[idle] sleeping");
-                // sleep and wait for interrupt
+                // sleep until the next timer event or interrupt, no need to tick meanwhile,
+                // unless a throttled real-time thread only gets its budget back on a `pick`
+                if needs_tick {
+                    timer::start_tick();
+                } else {
+                    timer::stop_tick();
+                }
                 trap::enable_and_wait();
             }
         }
     }
-    pub(crate) fn tick(&mut self) {
+    pub(crate) fn tick(&self) {
         let inner = self.inner();
         // println!("testing time slice");
-        if let Some((_, thread)) = &mut inner.cur {
-            // time's up
-            if inner.pool.tick() {
-                // println!("time is up");
-                let sstatus = trap::disable();
-                thread.switch(&mut inner.idle);
+        // time's up
+        if inner.tid.get().is_some() && inner.pool.lock().tick() {
+            // println!("time is up");
+            inner.resched.set(true);
+        } else {
+            // println!("you got some more time");
+        }
+        self.preempt();
+    }
+    /// switch out the current thread if its time slice has run out, unless it holds a spin lock
+    pub(crate) fn preempt(&self) {
+        // spin locks are taken long before there is anything to switch to
+        let inner = match self.inner.r#try() {
+            Some(inner) => inner,
+            None => return,
+        };
+        if inner.resched.get() && inner.tid.get().is_some() && preempt_count() == 0 {
+            inner.resched.set(false);
+            inner.preempted.set(true);
+            self.yield_now();
+        }
+    }
+    /// give up the CPU to the idle thread, the current thread stays runnable
+    pub(crate) fn yield_now(&self) {
+        assert_eq!(preempt_count(), 0, "yielding with preemption off");
+        let sstatus = trap::disable();
+        let (idle, cur) = unsafe { self.inner().threads() };
+        if let Some((_, thread)) = cur {
+            thread.switch(idle);
+        }
+        // back with the interrupt state we left with
+        trap::restore(sstatus);
+    }
+    /// block the current thread, `enqueue` arranges for someone to wake it up
+    pub(crate) fn park(&self, enqueue: impl FnOnce(TaskId)) {
+        assert_eq!(preempt_count(), 0, "blocking with preemption off");
+        let sstatus = trap::disable();
+        let inner = self.inner();
+        let tid = inner.tid.get().expect("only threads can block");
+        enqueue(tid);
+        inner.pool.lock().sleep(tid);
+        let (idle, cur) = unsafe { inner.threads() };
+        if let Some((_, thread)) = cur {
+            thread.switch(idle);
+        }
+        trap::restore(sstatus);
+    }
+    /// block the current thread until `deadline`
+    pub(crate) fn sleep_until(&self, deadline: Instant) {
+        if self.current().is_none() {
+            // the boot or idle thread has nobody to switch to
+            while Instant::now() < deadline {}
+            return;
+        }
+        self.park(|tid| {
+            timer::at(deadline, move || CPU.get().wake(tid));
+        });
+    }
+    /// make a sleeping thread runnable again, from interrupt context as well
+    pub(crate) fn wake(&self, tid: TaskId) {
+        if self.inner().pool.lock().wake(tid) {
+            // someone may need to be preempted for it
+            timer::start_tick();
+        }
+    }
+    pub(crate) fn set_priority(&self, tid: TaskId, prio: usize) {
+        self.inner().pool.lock().set_priority(tid, prio);
+    }
+    pub(crate) fn set_nice(&self, tid: TaskId, nice: isize) {
+        self.inner().pool.lock().set_nice(tid, nice);
+    }
+    pub(crate) fn set_deadline(
+        &self,
+        tid: TaskId,
+        params: Option<DeadlineParams>,
+    ) -> Result<(), AdmissionError> {
+        self.inner().pool.lock().set_deadline(tid, params)
+    }
+    pub(crate) fn stat(&self, tid: TaskId) -> Option<ThreadStat> {
+        self.inner().pool.lock().stat(tid)
+    }
+    pub(crate) fn ps(&self) -> Vec<ThreadMeta> {
+        let sstatus = trap::disable();
+        let inner = self.inner();
+        // the running one is not in the pool
+        let (_, cur) = unsafe { inner.threads() };
+        let running = cur.as_ref().map(|(tid, thread)| (*tid, &**thread));
+        let threads = inner.pool.lock().ps(running);
+        trap::restore(sstatus);
+        // the stacks are read with interrupts back on
+        threads
+            .into_iter()
+            .map(|(mut meta, stack)| {
+                meta.stack = stack.used();
+                meta
+            })
+            .collect()
+    }
+    /// block until `tid` exits, then free it and return its exit code
+    pub(crate) fn join(&self, tid: TaskId) -> ExitCode {
+        assert_eq!(preempt_count(), 0, "blocking with preemption off");
+        let sstatus = trap::disable();
+        loop {
+            let inner = self.inner();
+            let reaped = inner.pool.lock().reap(tid);
+            if let Some(code) = reaped {
                 trap::restore(sstatus);
-            } else {
-                // println!("you got some more time");
+                return code;
+            }
+            let me = inner.tid.get().expect("only threads can join");
+            inner.pool.lock().join(tid, me);
+            let (idle, cur) = unsafe { inner.threads() };
+            if let Some((_, thread)) = cur {
+                thread.switch(idle);
             }
         }
     }
-    pub(crate) fn exit(&mut self, code: usize) -> ! {
+    pub(crate) fn detach(&self, tid: TaskId) {
+        self.inner().pool.lock().detach(tid);
+    }
+    pub(crate) fn exit(&self, code: usize) -> ! {
+        // the next thread on this hart would inherit the count
+        assert_eq!(preempt_count(), 0, "exiting with preemption off");
         trap::disable();
         let inner = self.inner();
-        let (tid, thread) = inner.cur.as_mut().expect("thread to exist must be running");
-        println!("thread {} exited, with code {}", tid, code);
-        inner.pool.exit(*tid, code);
-        thread.switch(&mut inner.idle);
+        let (idle, cur) = unsafe { inner.threads() };
+        let (tid, thread) = cur.as_mut().expect("thread to exist must be running");
+        if TRACE.load(Ordering::Relaxed) {
+            println!(
+                "thread {} ({}) exited, with code {}",
+                tid,
+                thread.name(),
+                code
+            );
+        }
+        if let Some(pid) = thread.pid() {
+            // the last one out tears down the address space, we are still in it
+            process::thread_exited(pid, *tid, code);
+        }
+        inner.pool.lock().exit(*tid, code);
+        thread.switch(idle);
         assert!(false, "this should not be reachable");
         loop {}
     }
-    pub(crate) fn run(&mut self) {
-        Thread::boot_thread().switch(&mut self.inner().idle);
+    pub(crate) fn set_satp(&self, satp: usize) {
+        let sstatus = trap::disable();
+        let (_, cur) = unsafe { self.inner().threads() };
+        let (_, thread) = cur.as_mut().expect("only threads have an address space");
+        thread.context.satp = satp;
+        unsafe {
+            asm!("csrw satp, $0; sfence.vma" :: "r"(satp) :: "volatile");
+        }
+        trap::restore(sstatus);
+    }
+    pub(crate) fn run(&self) {
+        trap::disable();
+        let (idle, _) = unsafe { self.inner().threads() };
+        Thread::boot_thread().switch(idle);
     }
 }
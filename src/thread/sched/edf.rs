@@ -0,0 +1,279 @@
+use crate::config::*;
+use crate::thread::sched::{Scheduler, Slot};
+use crate::time::{self, Duration, Instant};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// real-time threads together may use at most `RT_RUNTIME` out of every `RT_PERIOD` ticks,
+/// the rest is left to the normal class
+const RT_PERIOD: u64 = 100;
+const RT_RUNTIME: u64 = 95;
+/// fixed point unit of utilization
+const UNIT: u64 = 1 << 20;
+
+/// what a real-time thread asks for, in ticks:
+/// `runtime` of CPU time within `deadline` after the start of every `period`
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct DeadlineParams {
+    pub(crate) runtime: u64,
+    pub(crate) deadline: u64,
+    pub(crate) period: u64,
+}
+
+impl DeadlineParams {
+    pub(crate) fn new(runtime: Duration, deadline: Duration, period: Duration) -> Self {
+        let ticks = |d: Duration| time::to_cycles(d) * TICKS_PER_SECOND / time::frequency();
+        Self {
+            runtime: ticks(runtime),
+            deadline: ticks(deadline),
+            period: ticks(period),
+        }
+    }
+    fn utilization(&self) -> u64 {
+        self.runtime * UNIT / self.period
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum AdmissionError {
+    /// the scheduler has no real-time class
+    Unsupported,
+    /// runtime <= deadline <= period does not hold, or runtime is zero
+    Invalid,
+    /// the thread has exited
+    NoSuchThread,
+    /// the real-time threads would need more than their share of the CPU
+    Overcommitted,
+}
+
+#[derive(Copy, Clone)]
+struct RtInfo {
+    params: DeadlineParams,
+    /// absolute deadline of the current job, in ticks
+    deadline: u64,
+    /// start of the next period, in ticks
+    next_period: u64,
+    /// ticks left in the current job
+    budget: u64,
+    /// deadlines missed so far
+    missed: usize,
+}
+
+/// earliest deadline first real-time class on top of a normal scheduler
+///
+/// admitted threads always run before normal ones, the one with the earliest
+/// deadline first, until their budget for the period or the class bandwidth is used up
+pub(crate) struct EdfScheduler {
+    normal: Box<dyn Scheduler + Send>,
+    rt: Vec<Option<RtInfo>>,
+    /// runnable real-time threads
+    ready: Vec<Slot>,
+    /// the running thread, and whether it was picked by the real-time class
+    current: Option<(Slot, bool)>,
+    /// start of the current bandwidth window and real-time ticks used in it
+    window: u64,
+    used: u64,
+}
+impl Scheduler for EdfScheduler {
+    fn push(&mut self, tid: Slot) {
+        if self.is_rt(tid) {
+            self.ready.push(tid);
+        } else {
+            self.normal.push(tid);
+        }
+    }
+    fn pick(&mut self) -> Option<Slot> {
+        self.refresh(now());
+        if let Some(idx) = self.earliest() {
+            let tid = self.ready.remove(idx);
+            self.current = Some((tid, true));
+            return Some(tid);
+        }
+        let tid = self.normal.pick()?;
+        self.current = Some((tid, false));
+        Some(tid)
+    }
+    fn r#yield(&mut self, tid: Slot) {
+        self.put_back(tid, false);
+    }
+    fn preempt(&mut self, tid: Slot) {
+        self.put_back(tid, true);
+    }
+    fn tick(&mut self) -> bool {
+        let now = now();
+        self.refresh(now);
+        match self.current {
+            Some((tid, true)) => {
+                self.used += 1;
+                let earliest = self.earliest().map(|idx| self.deadline(self.ready[idx]));
+                let info = match &mut self.rt[tid] {
+                    Some(info) => info,
+                    // it has left the real-time class meanwhile
+                    None => return true,
+                };
+                info.budget = info.budget.saturating_sub(1);
+                if info.budget != 0 && now > info.deadline {
+                    info.missed += 1;
+                    println!(
+                        "real-time thread {} missed its deadline, {} so far",
+                        tid, info.missed
+                    );
+                    // only count once per job
+                    info.deadline = info.next_period + info.params.deadline;
+                }
+                info.budget == 0
+                    || self.used >= RT_RUNTIME
+                    || earliest.map_or(false, |d| d < info.deadline)
+            }
+            Some((_, false)) => {
+                let expired = self.normal.tick();
+                // real-time threads preempt normal ones right away
+                expired || self.earliest().is_some()
+            }
+            None => false,
+        }
+    }
+    fn exit(&mut self, tid: Slot) {
+        if self.current.map_or(false, |(t, _)| t == tid) {
+            self.current = None;
+        }
+        if self.is_rt(tid) {
+            self.ready.retain(|&t| t != tid);
+            self.rt[tid] = None;
+        } else {
+            self.normal.exit(tid);
+        }
+    }
+    fn sleep(&mut self, tid: Slot) {
+        if self.current.map_or(false, |(t, _)| t == tid) {
+            self.current = None;
+        }
+        if self.is_rt(tid) {
+            self.ready.retain(|&t| t != tid);
+        } else {
+            self.normal.sleep(tid);
+        }
+    }
+    fn set_priority(&mut self, tid: Slot, prio: usize) {
+        self.normal.set_priority(tid, prio);
+    }
+    fn set_nice(&mut self, tid: Slot, nice: isize) {
+        self.normal.set_nice(tid, nice);
+    }
+    fn set_deadline(
+        &mut self,
+        tid: Slot,
+        params: Option<DeadlineParams>,
+    ) -> Result<(), AdmissionError> {
+        if tid >= self.rt.len() {
+            self.rt.resize(tid + 1, None);
+        }
+        let params = match params {
+            Some(params) => params,
+            None => {
+                // a running one goes to the normal class once it yields
+                self.rt[tid] = None;
+                return Ok(());
+            }
+        };
+        if params.runtime == 0
+            || params.runtime > params.deadline
+            || params.deadline > params.period
+        {
+            return Err(AdmissionError::Invalid);
+        }
+        let others: u64 = self
+            .rt
+            .iter()
+            .enumerate()
+            .filter(|&(t, _)| t != tid)
+            .filter_map(|(_, info)| info.map(|info| info.params.utilization()))
+            .sum();
+        if others + params.utilization() > RT_RUNTIME * UNIT / RT_PERIOD {
+            return Err(AdmissionError::Overcommitted);
+        }
+        // a running normal thread leaves the normal class now, its ticks go to its budget
+        if !self.is_rt(tid) && self.current == Some((tid, false)) {
+            self.normal.sleep(tid);
+            self.current = Some((tid, true));
+        }
+        let now = now();
+        self.rt[tid] = Some(RtInfo {
+            params,
+            deadline: now + params.deadline,
+            next_period: now + params.period,
+            budget: params.runtime,
+            missed: 0,
+        });
+        Ok(())
+    }
+    fn needs_tick(&self) -> bool {
+        // budgets are only enforced on ticks, and refilled on ticks or picks
+        self.rt.iter().any(Option::is_some)
+    }
+}
+impl EdfScheduler {
+    pub(crate) fn new(normal: Box<dyn Scheduler + Send>) -> Self {
+        Self {
+            normal,
+            rt: Vec::new(),
+            ready: Vec::new(),
+            current: None,
+            window: 0,
+            used: 0,
+        }
+    }
+    fn is_rt(&self, tid: Slot) -> bool {
+        self.rt.get(tid).map_or(false, Option::is_some)
+    }
+    fn deadline(&self, tid: Slot) -> u64 {
+        self.rt[tid].map_or(u64::max_value(), |info| info.deadline)
+    }
+    /// the ready real-time thread to run next, None if there is none or the class is throttled
+    fn earliest(&self) -> Option<usize> {
+        if self.used >= RT_RUNTIME {
+            return None;
+        }
+        self.ready
+            .iter()
+            .enumerate()
+            .filter(|&(_, &tid)| self.rt[tid].map_or(false, |info| info.budget != 0))
+            .min_by_key(|&(_, &tid)| self.deadline(tid))
+            .map(|(idx, _)| idx)
+    }
+    /// start new bandwidth windows and new jobs whose time has come
+    fn refresh(&mut self, now: u64) {
+        if now >= self.window + RT_PERIOD {
+            self.window = now;
+            self.used = 0;
+        }
+        for info in self.rt.iter_mut().filter_map(Option::as_mut) {
+            while now >= info.next_period {
+                info.deadline = info.next_period + info.params.deadline;
+                info.next_period += info.params.period;
+                info.budget = info.params.runtime;
+            }
+        }
+    }
+    fn put_back(&mut self, tid: Slot, preempted: bool) {
+        match self.current {
+            Some((t, from_rt)) if t == tid => {
+                self.current = None;
+                match (from_rt, self.is_rt(tid)) {
+                    (_, true) => self.ready.push(tid),
+                    // it has left the real-time class while running
+                    (true, false) => self.normal.push(tid),
+                    (false, false) if preempted => self.normal.preempt(tid),
+                    (false, false) => self.normal.r#yield(tid),
+                }
+            }
+            _ if preempted => self.normal.preempt(tid),
+            _ => self.normal.r#yield(tid),
+        }
+    }
+}
+
+/// the monotonic clock in ticks, which keeps going while the tick is stopped
+fn now() -> u64 {
+    Instant::now().cycles() * TICKS_PER_SECOND / time::frequency()
+}
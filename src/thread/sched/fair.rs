@@ -0,0 +1,152 @@
+use crate::thread::sched::{Scheduler, Slot};
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::cmp::max;
+
+/// every runnable thread should get to run once within this many ticks
+const LATENCY: u64 = 20;
+/// but no slice is shorter than this many ticks
+const MIN_GRANULARITY: u64 = 2;
+/// vruntime a nice 0 thread accumulates in one tick
+const TICK_VRUNTIME: u64 = 1 << 20;
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// weight of nice -20 to 19, each step is roughly 10% of CPU time, taken from Linux
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+#[derive(Copy, Clone)]
+struct FairInfo {
+    vruntime: u64,
+    weight: u64,
+}
+impl Default for FairInfo {
+    fn default() -> Self {
+        Self {
+            vruntime: 0,
+            weight: NICE_0_WEIGHT,
+        }
+    }
+}
+
+/// completely fair scheduler, the thread that has had the least weighted CPU time runs next
+///
+/// a thread's virtual runtime advances inversely proportional to its weight,
+/// and its time slice is its weighted share of `LATENCY`
+pub(crate) struct FairScheduler {
+    /// ready threads ordered by vruntime
+    tree: BTreeSet<(u64, Slot)>,
+    info: Vec<FairInfo>,
+    current: Option<Slot>,
+    /// ticks the current thread has run, and the ticks it is allowed to
+    ran: u64,
+    slice: u64,
+    /// total weight of ready and running threads
+    load: u64,
+    /// never goes backwards, where newcomers and sleepers are placed
+    min_vruntime: u64,
+}
+impl Scheduler for FairScheduler {
+    fn push(&mut self, tid: Slot) {
+        // a newcomer or a thread that has been sleeping must not
+        // monopolize the CPU to catch up with the others
+        let min = self.min_vruntime;
+        let info = self.info(tid);
+        info.vruntime = max(info.vruntime, min);
+        let FairInfo { vruntime, weight } = *info;
+        self.tree.insert((vruntime, tid));
+        self.load += weight;
+    }
+    fn pick(&mut self) -> Option<Slot> {
+        let first = self.tree.iter().next().cloned();
+        first.map(|(vruntime, tid)| {
+            self.tree.remove(&(vruntime, tid));
+            self.current = Some(tid);
+            self.ran = 0;
+            let weight = self.info(tid).weight;
+            self.slice = max(MIN_GRANULARITY, LATENCY * weight / max(self.load, 1));
+            tid
+        })
+    }
+    fn r#yield(&mut self, tid: Slot) {
+        if self.current == Some(tid) {
+            self.current = None;
+            // yielding before the first tick would leave it the leftmost forever
+            if self.ran == 0 {
+                self.charge(tid);
+            }
+            let vruntime = self.info(tid).vruntime;
+            self.tree.insert((vruntime, tid));
+        }
+    }
+    fn tick(&mut self) -> bool {
+        match self.current {
+            Some(tid) => {
+                self.charge(tid);
+                self.ran += 1;
+                self.ran >= self.slice
+            }
+            None => false,
+        }
+    }
+    fn exit(&mut self, tid: Slot) {
+        self.remove(tid);
+        // the id will be reused by somebody else
+        *self.info(tid) = FairInfo::default();
+    }
+    fn sleep(&mut self, tid: Slot) {
+        self.remove(tid);
+    }
+    fn set_nice(&mut self, tid: Slot, nice: isize) {
+        let weight = NICE_TO_WEIGHT[(nice.max(-20).min(19) + 20) as usize];
+        let runnable = self.current == Some(tid)
+            || self
+                .tree
+                .contains(&(self.info.get(tid).map_or(0, |i| i.vruntime), tid));
+        let info = self.info(tid);
+        let old = core::mem::replace(&mut info.weight, weight);
+        if runnable {
+            self.load = self.load - old + weight;
+        }
+    }
+}
+impl FairScheduler {
+    pub(crate) fn new() -> Self {
+        Self {
+            tree: BTreeSet::new(),
+            info: Vec::new(),
+            current: None,
+            ran: 0,
+            slice: 0,
+            load: 0,
+            min_vruntime: 0,
+        }
+    }
+    fn info(&mut self, tid: Slot) -> &mut FairInfo {
+        if tid >= self.info.len() {
+            self.info.resize_with(tid + 1, Default::default);
+        }
+        &mut self.info[tid]
+    }
+    /// account one tick of CPU time to `tid`
+    fn charge(&mut self, tid: Slot) {
+        let info = self.info(tid);
+        info.vruntime += TICK_VRUNTIME * NICE_0_WEIGHT / info.weight;
+        let current = info.vruntime;
+        let leftmost = self.tree.iter().next().map_or(current, |&(v, _)| v);
+        self.min_vruntime = max(self.min_vruntime, current.min(leftmost));
+    }
+    /// take a thread off the CPU or out of the tree
+    fn remove(&mut self, tid: Slot) {
+        let FairInfo { vruntime, weight } = *self.info(tid);
+        if self.current == Some(tid) {
+            self.current = None;
+        } else if !self.tree.remove(&(vruntime, tid)) {
+            return;
+        }
+        self.load -= weight;
+    }
+}
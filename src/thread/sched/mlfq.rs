@@ -0,0 +1,107 @@
+use crate::thread::sched::queue::RunQueue;
+use crate::thread::sched::{Scheduler, Slot};
+use alloc::vec::Vec;
+
+const LEVELS: usize = 4;
+/// time slice in ticks of each level, lower levels run longer
+const SLICES: [usize; LEVELS] = [2, 4, 8, 16];
+/// everyone goes back to the top level this often, in ticks, so that nobody starves
+const BOOST_PERIOD: usize = 200;
+
+/// multi-level feedback queue scheduler
+///
+/// threads that use up their time slice sink to a lower level with a longer slice,
+/// threads that yield or block early stay where they are
+pub(crate) struct MlfqScheduler {
+    /// level 0 is the highest
+    queues: [RunQueue; LEVELS],
+    level: Vec<usize>,
+    current: Option<Slot>,
+    /// ticks left for the current thread
+    time: usize,
+    since_boost: usize,
+}
+impl Scheduler for MlfqScheduler {
+    fn push(&mut self, tid: Slot) {
+        let level = self.level(tid);
+        self.queues[level].push_back(tid);
+    }
+    fn pick(&mut self) -> Option<Slot> {
+        let level = self.queues.iter().position(|q| !q.is_empty())?;
+        let tid = self.queues[level].pop_front()?;
+        self.current = Some(tid);
+        self.time = SLICES[level];
+        Some(tid)
+    }
+    fn r#yield(&mut self, tid: Slot) {
+        if self.current == Some(tid) {
+            self.current = None;
+            self.push(tid);
+        }
+    }
+    fn preempt(&mut self, tid: Slot) {
+        if self.current == Some(tid) {
+            self.current = None;
+            let level = self.level(tid);
+            self.level[tid] = (level + 1).min(LEVELS - 1);
+            self.push(tid);
+        }
+    }
+    fn tick(&mut self) -> bool {
+        self.since_boost += 1;
+        if self.since_boost >= BOOST_PERIOD {
+            self.boost();
+        }
+        if self.current.is_some() && self.time > 0 {
+            self.time -= 1;
+        }
+        self.current.is_some() && self.time == 0
+    }
+    fn exit(&mut self, tid: Slot) {
+        self.remove(tid);
+        // the id will be reused by somebody else
+        self.level(tid);
+        self.level[tid] = 0;
+    }
+    fn sleep(&mut self, tid: Slot) {
+        self.remove(tid);
+    }
+}
+impl MlfqScheduler {
+    pub(crate) fn new() -> Self {
+        Self {
+            queues: Default::default(),
+            level: Vec::new(),
+            current: None,
+            time: 0,
+            since_boost: 0,
+        }
+    }
+    fn level(&mut self, tid: Slot) -> usize {
+        if tid >= self.level.len() {
+            self.level.resize(tid + 1, 0);
+        }
+        self.level[tid]
+    }
+    /// move everyone back to the top level
+    fn boost(&mut self) {
+        self.since_boost = 0;
+        for level in self.level.iter_mut() {
+            *level = 0;
+        }
+        for level in 1..LEVELS {
+            while let Some(tid) = self.queues[level].pop_front() {
+                self.queues[0].push_back(tid);
+            }
+        }
+    }
+    /// take a thread off the CPU or out of its queue
+    fn remove(&mut self, tid: Slot) {
+        if self.current == Some(tid) {
+            self.current = None;
+        } else {
+            let level = self.level(tid);
+            self.queues[level].remove(tid);
+        }
+    }
+}
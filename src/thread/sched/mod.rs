@@ -0,0 +1,479 @@
+use crate::process::Pid;
+use crate::thread::stat::{self, ThreadStat};
+use crate::thread::{StackBounds, Thread};
+use crate::time::{Duration, Instant};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+mod edf;
+mod fair;
+mod mlfq;
+mod priority;
+mod queue;
+mod rr;
+mod stride;
+
+pub(crate) use edf::{AdmissionError, DeadlineParams, EdfScheduler};
+pub(crate) use fair::FairScheduler;
+pub(crate) use mlfq::MlfqScheduler;
+pub(crate) use priority::PriorityScheduler;
+pub(crate) use rr::RRScheduler;
+pub(crate) use stride::StrideScheduler;
+
+/// the scheduler called `name`, with the real-time class on top
+pub(crate) fn by_name(name: &str) -> Option<Box<dyn Scheduler + Send>> {
+    let normal: Box<dyn Scheduler + Send> = match name {
+        "rr" => Box::new(RRScheduler::new()),
+        "stride" => Box::new(StrideScheduler::new()),
+        "priority" => Box::new(PriorityScheduler::new()),
+        "fair" => Box::new(FairScheduler::new()),
+        "mlfq" => Box::new(MlfqScheduler::new()),
+        _ => return None,
+    };
+    Some(Box::new(EdfScheduler::new(normal)))
+}
+
+/// the scheduler chosen by a `sched-*` cargo feature, round robin without one
+pub(crate) fn default_name() -> &'static str {
+    if cfg!(feature = "sched-stride") {
+        "stride"
+    } else if cfg!(feature = "sched-priority") {
+        "priority"
+    } else if cfg!(feature = "sched-fair") {
+        "fair"
+    } else if cfg!(feature = "sched-mlfq") {
+        "mlfq"
+    } else {
+        "rr"
+    }
+}
+
+/// index into the thread table, which is what schedulers work with
+pub(crate) type Slot = usize;
+
+/// identifies a thread: its slot in the thread table, and how many times the slot has been reused
+///
+/// so that a stale id of an exited thread never refers to the next one in the same slot
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct TaskId {
+    slot: Slot,
+    gen: usize,
+}
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use core::fmt::Write;
+        // formatted on the stack, the watchdog prints task ids from interrupt context
+        // where the heap lock may already be held
+        let mut buf = StackBuf {
+            buf: [0; 2 * 20 + 1],
+            len: 0,
+        };
+        if self.gen == 0 {
+            write!(buf, "{}", self.slot)?;
+        } else {
+            write!(buf, "{}.{}", self.slot, self.gen)?;
+        }
+        f.pad(buf.as_str())
+    }
+}
+
+/// enough for two `usize`s and a separator
+struct StackBuf {
+    buf: [u8; 2 * 20 + 1],
+    len: usize,
+}
+impl StackBuf {
+    fn as_str(&self) -> &str {
+        // only ever filled by `write_str`
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+}
+impl fmt::Write for StackBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+impl fmt::Debug for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+pub(crate) type ExitCode = usize;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum SpawnError {
+    /// there are `MAX_THREADS` already
+    TooManyThreads,
+    /// the process to start it in has exited, or never was
+    NoSuchProcess,
+}
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Status {
+    /// there is no thread
+    Uninitialized,
+    /// waiting for resources
+    Ready,
+    /// running
+    Running(TaskId),
+    /// blocked
+    Sleeping,
+    /// finished
+    Exited(ExitCode),
+}
+
+pub(crate) trait Scheduler {
+    /// add a task for scheduling
+    fn push(&mut self, _: Slot);
+    // pick one for running
+    fn pick(&mut self) -> Option<Slot>;
+    // give up resources
+    fn r#yield(&mut self, tid: Slot);
+    // the time slice handed out by `tick` is used up
+    fn preempt(&mut self, tid: Slot) {
+        self.r#yield(tid)
+    }
+    // timer interrupt will trigger this
+    // true means time is up
+    fn tick(&mut self) -> bool;
+    // a thread has finished
+    fn exit(&mut self, _: Slot);
+    // a thread is blocked, it leaves the queue until pushed again
+    fn sleep(&mut self, tid: Slot) {
+        self.exit(tid)
+    }
+    // larger means more important, ignored by schedulers without priorities
+    fn set_priority(&mut self, _tid: Slot, _prio: usize) {}
+    // -20 (greedy) to 19 (nice), ignored by schedulers without weights
+    fn set_nice(&mut self, _tid: Slot, _nice: isize) {}
+    // join the real-time class, or leave it with None
+    fn set_deadline(
+        &mut self,
+        _tid: Slot,
+        _params: Option<DeadlineParams>,
+    ) -> Result<(), AdmissionError> {
+        Err(AdmissionError::Unsupported)
+    }
+    // keep the periodic tick running even when there is nobody else to switch to
+    fn needs_tick(&self) -> bool {
+        false
+    }
+}
+
+struct ThreadInfo {
+    /// bumped whenever the slot is freed
+    gen: usize,
+    status: Status,
+    thread: Option<Box<Thread>>,
+    /// the thread waiting in `join` for this one to exit
+    joiner: Option<TaskId>,
+    /// nobody is going to join, reap it as soon as it exits
+    detached: bool,
+    /// who started it, None for those started by the boot or idle thread
+    parent: Option<TaskId>,
+    stat: ThreadStat,
+    /// when it entered the current status
+    since: Instant,
+}
+impl Default for ThreadInfo {
+    fn default() -> Self {
+        Self {
+            gen: 0,
+            status: Status::Uninitialized,
+            thread: None,
+            joiner: None,
+            detached: false,
+            parent: None,
+            stat: ThreadStat::new(),
+            since: Instant::from_cycles(0),
+        }
+    }
+}
+/// what `ps` shows about a thread
+#[derive(Debug, Clone)]
+pub(crate) struct ThreadMeta {
+    pub(crate) tid: TaskId,
+    pub(crate) name: String,
+    pub(crate) parent: Option<TaskId>,
+    /// None for kernel threads
+    pub(crate) pid: Option<Pid>,
+    pub(crate) status: Status,
+    pub(crate) created: Instant,
+    /// time spent on the CPU
+    pub(crate) cpu: Duration,
+    /// deepest its kernel stack has ever been, in bytes
+    pub(crate) stack: usize,
+}
+pub(crate) struct ThreadPool {
+    /// indexed by slot, grows on demand up to `max`
+    threads: Vec<ThreadInfo>,
+    /// the Uninitialized slots
+    free: Vec<Slot>,
+    max: usize,
+    scheduler: Box<dyn Scheduler + Send>,
+    /// number of threads in Ready status
+    ready: usize,
+}
+impl ThreadPool {
+    /// create a thread pool for at most `max` threads with the given scheduler
+    pub(crate) fn new(max: usize, scheduler: Box<dyn Scheduler + Send>) -> Self {
+        Self {
+            threads: Vec::new(),
+            free: Vec::new(),
+            max,
+            scheduler,
+            ready: 0,
+        }
+    }
+    /// allocate a slot, exited threads keep theirs until reaped
+    fn alloc(&mut self) -> Result<Slot, SpawnError> {
+        if let Some(slot) = self.free.pop() {
+            return Ok(slot);
+        }
+        if self.threads.len() == self.max {
+            return Err(SpawnError::TooManyThreads);
+        }
+        self.threads.push(ThreadInfo::default());
+        Ok(self.threads.len() - 1)
+    }
+    /// drop the thread and give its slot back, -> Uninitialized
+    fn release(&mut self, slot: Slot) {
+        let gen = self.threads[slot].gen.wrapping_add(1);
+        self.threads[slot] = ThreadInfo {
+            gen,
+            ..ThreadInfo::default()
+        };
+        self.free.push(slot);
+    }
+    /// the slot of `tid`, None if the thread is gone
+    fn slot(&self, tid: TaskId) -> Option<Slot> {
+        match self.threads.get(tid.slot) {
+            Some(tinfo) if tinfo.gen == tid.gen => Some(tid.slot),
+            _ => None,
+        }
+    }
+    fn id(&self, slot: Slot) -> TaskId {
+        TaskId {
+            slot,
+            gen: self.threads[slot].gen,
+        }
+    }
+    /// add a runnable thread, None -> Ready
+    pub(crate) fn push(
+        &mut self,
+        t: Box<Thread>,
+        parent: Option<TaskId>,
+    ) -> Result<TaskId, SpawnError> {
+        let slot = self.alloc()?;
+        let tinfo = &mut self.threads[slot];
+        tinfo.status = Status::Ready;
+        tinfo.thread = Some(t);
+        tinfo.parent = parent;
+        tinfo.since = Instant::now();
+        self.ready += 1;
+        self.scheduler.push(slot);
+        Ok(self.id(slot))
+    }
+    /// pick one to run, Ready -> Running
+    pub(crate) fn pick(&mut self) -> Option<(TaskId, Box<Thread>)> {
+        let slot = self.scheduler.pick()?;
+        let tid = self.id(slot);
+        let tinfo = &mut self.threads[slot];
+        tinfo.status = Status::Running(tid);
+        let now = Instant::now();
+        tinfo.stat.wait += now - tinfo.since;
+        tinfo.stat.switches += 1;
+        tinfo.since = now;
+        self.ready -= 1;
+        Some((tid, tinfo.thread.take().expect("no such thread exists")))
+    }
+    /// give up the resources, Running -> Ready
+    ///
+    /// `preempted` tells a used up time slice from a voluntary yield
+    pub(crate) fn r#yield(&mut self, tid: TaskId, thread: Box<Thread>, preempted: bool) {
+        let slot = tid.slot;
+        let tinfo = &mut self.threads[slot];
+        tinfo.thread = Some(thread);
+        let now = Instant::now();
+        let run = now - tinfo.since;
+        tinfo.stat.run += run;
+        tinfo.since = now;
+        if preempted {
+            tinfo.stat.preemptions += 1;
+        }
+        stat::record(run, preempted);
+        match self.threads[slot].status {
+            Status::Running(_) => {
+                self.threads[slot].status = Status::Ready;
+                self.ready += 1;
+                if preempted {
+                    self.scheduler.preempt(slot);
+                } else {
+                    self.scheduler.r#yield(slot);
+                }
+            }
+            // it is off its stack now, safe to free
+            Status::Exited(_) if self.threads[slot].detached => self.release(slot),
+            _ => {}
+        }
+    }
+    pub(crate) fn set_priority(&mut self, tid: TaskId, prio: usize) {
+        if let Some(slot) = self.slot(tid) {
+            self.scheduler.set_priority(slot, prio);
+        }
+    }
+    pub(crate) fn set_nice(&mut self, tid: TaskId, nice: isize) {
+        if let Some(slot) = self.slot(tid) {
+            self.scheduler.set_nice(slot, nice);
+        }
+    }
+    pub(crate) fn set_deadline(
+        &mut self,
+        tid: TaskId,
+        params: Option<DeadlineParams>,
+    ) -> Result<(), AdmissionError> {
+        let slot = self.slot(tid).ok_or(AdmissionError::NoSuchThread)?;
+        // take a waiting thread out of the queue of its old class, and into the new one
+        let ready = if let Status::Ready = self.threads[slot].status {
+            true
+        } else {
+            false
+        };
+        if ready {
+            self.scheduler.sleep(slot);
+        }
+        let result = self.scheduler.set_deadline(slot, params);
+        if ready {
+            self.scheduler.push(slot);
+        }
+        result
+    }
+    pub(crate) fn needs_tick(&self) -> bool {
+        self.scheduler.needs_tick()
+    }
+    /// a snapshot of the statistics of `tid`, None if there is no such thread
+    pub(crate) fn stat(&self, tid: TaskId) -> Option<ThreadStat> {
+        let tinfo = &self.threads[self.slot(tid)?];
+        let mut stat = tinfo.stat;
+        // count what has passed in the current status as well
+        match tinfo.status {
+            Status::Uninitialized => return None,
+            Status::Ready => stat.wait += tinfo.since.elapsed(),
+            Status::Running(_) => stat.run += tinfo.since.elapsed(),
+            _ => {}
+        }
+        Some(stat)
+    }
+    /// metadata of every thread, `running` is the one currently off the pool
+    ///
+    /// `stack` is left at zero, the stacks are for the caller to measure without the pool locked
+    pub(crate) fn ps(&self, running: Option<(TaskId, &Thread)>) -> Vec<(ThreadMeta, StackBounds)> {
+        (0..self.threads.len())
+            .filter_map(|slot| {
+                let tid = self.id(slot);
+                let tinfo = &self.threads[slot];
+                let thread = match (&tinfo.thread, running) {
+                    (Some(thread), _) => &**thread,
+                    (None, Some((t, thread))) if t == tid => thread,
+                    _ => return None,
+                };
+                let meta = ThreadMeta {
+                    tid,
+                    name: String::from(thread.name()),
+                    parent: tinfo.parent,
+                    pid: thread.pid(),
+                    status: tinfo.status,
+                    created: thread.created(),
+                    cpu: self.stat(tid)?.run,
+                    stack: 0,
+                };
+                Some((meta, thread.stack_bounds()))
+            })
+            .collect()
+    }
+    /// number of threads waiting to run
+    pub(crate) fn ready(&self) -> usize {
+        self.ready
+    }
+    pub(crate) fn tick(&mut self) -> bool {
+        self.scheduler.tick()
+    }
+    /// block a thread, Running -> Sleeping
+    pub(crate) fn sleep(&mut self, tid: TaskId) {
+        self.threads[tid.slot].status = Status::Sleeping;
+        self.scheduler.sleep(tid.slot);
+    }
+    /// make a blocked thread runnable again, Sleeping -> Ready
+    ///
+    /// false if it is not sleeping, or gone already
+    pub(crate) fn wake(&mut self, tid: TaskId) -> bool {
+        let slot = match self.slot(tid) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        if let Status::Sleeping = self.threads[slot].status {
+            self.threads[slot].status = Status::Ready;
+            self.threads[slot].since = Instant::now();
+            self.ready += 1;
+            self.scheduler.push(slot);
+            true
+        } else {
+            false
+        }
+    }
+    pub(crate) fn exit(&mut self, tid: TaskId, code: usize) {
+        self.threads[tid.slot].status = Status::Exited(code);
+        self.scheduler.exit(tid.slot);
+        if let Some(joiner) = self.threads[tid.slot].joiner.take() {
+            self.wake(joiner);
+        }
+    }
+    /// free an exited thread and get its exit code, Exited -> None
+    ///
+    /// None if it has not exited yet, or is still on its way off the CPU
+    pub(crate) fn reap(&mut self, tid: TaskId) -> Option<ExitCode> {
+        let slot = match self.slot(tid) {
+            Some(slot) => slot,
+            None => panic!("joining thread {} that does not exist", tid),
+        };
+        let tinfo = &self.threads[slot];
+        match tinfo.status {
+            Status::Uninitialized => panic!("joining thread {} that does not exist", tid),
+            Status::Exited(code) if tinfo.thread.is_some() => {
+                self.release(slot);
+                Some(code)
+            }
+            _ => None,
+        }
+    }
+    /// block `joiner` until `tid` exits, Running -> Sleeping
+    pub(crate) fn join(&mut self, tid: TaskId, joiner: TaskId) {
+        assert!(
+            self.threads[tid.slot].joiner.is_none(),
+            "thread {} is already being joined",
+            tid
+        );
+        self.threads[tid.slot].joiner = Some(joiner);
+        self.sleep(joiner);
+    }
+    /// nobody is going to join `tid`, free it once it exits
+    pub(crate) fn detach(&mut self, tid: TaskId) {
+        let slot = match self.slot(tid) {
+            Some(slot) => slot,
+            None => return,
+        };
+        match self.threads[slot].status {
+            Status::Uninitialized => {}
+            Status::Exited(_) if self.threads[slot].thread.is_some() => self.release(slot),
+            _ => self.threads[slot].detached = true,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::config::*;
+use crate::thread::sched::queue::SlotHeap;
+use crate::thread::sched::{Scheduler, Slot};
+use alloc::vec::Vec;
+
+/// strict priority scheduler, the most important ready thread always runs next
+///
+/// threads of the same priority take turns in FIFO order
+pub(crate) struct PriorityScheduler {
+    /// ready threads by priority
+    queue: SlotHeap<usize>,
+    priority: Vec<usize>,
+    current: Option<Slot>,
+    /// ticks left for the current thread
+    time: usize,
+}
+impl Scheduler for PriorityScheduler {
+    fn push(&mut self, tid: Slot) {
+        let prio = self.priority(tid);
+        self.queue.push(tid, prio);
+    }
+    fn pick(&mut self) -> Option<Slot> {
+        self.queue.pop().map(|(_, tid)| {
+            self.current = Some(tid);
+            self.time = TICKS_PER_TIME_SLICE;
+            tid
+        })
+    }
+    fn r#yield(&mut self, tid: Slot) {
+        if self.current == Some(tid) {
+            self.current = None;
+            self.push(tid);
+        }
+    }
+    fn tick(&mut self) -> bool {
+        if self.current.is_some() && self.time > 0 {
+            self.time -= 1;
+        }
+        self.current.is_some() && self.time == 0
+    }
+    fn exit(&mut self, tid: Slot) {
+        self.remove(tid);
+        // the id will be reused by somebody else
+        self.set_priority(tid, DEFAULT_PRIORITY);
+    }
+    fn sleep(&mut self, tid: Slot) {
+        self.remove(tid);
+    }
+    fn set_priority(&mut self, tid: Slot, prio: usize) {
+        self.priority(tid);
+        self.priority[tid] = prio;
+        // requeue with the new priority if it is waiting
+        if self.queue.contains(tid) {
+            self.queue.push(tid, prio);
+        }
+    }
+}
+impl PriorityScheduler {
+    pub(crate) fn new() -> Self {
+        Self {
+            queue: SlotHeap::new(),
+            priority: Vec::new(),
+            current: None,
+            time: 0,
+        }
+    }
+    fn priority(&mut self, tid: Slot) -> usize {
+        if tid >= self.priority.len() {
+            self.priority.resize(tid + 1, DEFAULT_PRIORITY);
+        }
+        self.priority[tid]
+    }
+    /// take a thread off the CPU or out of the queue
+    fn remove(&mut self, tid: Slot) {
+        if self.current == Some(tid) {
+            self.current = None;
+        } else {
+            self.queue.remove(tid);
+        }
+    }
+}
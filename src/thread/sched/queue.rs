@@ -0,0 +1,184 @@
+use crate::thread::sched::Slot;
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+const NIL: Slot = usize::max_value();
+
+#[derive(Copy, Clone)]
+struct Link {
+    prev: Slot,
+    next: Slot,
+    queued: bool,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self {
+            prev: NIL,
+            next: NIL,
+            queued: false,
+        }
+    }
+}
+
+/// FIFO of task ids with O(1) push, pop and removal of any member
+///
+/// the links live in a table indexed by tid rather than in nodes of their own,
+/// so a tid can be in one queue at a time only
+pub(crate) struct RunQueue {
+    links: Vec<Link>,
+    head: Slot,
+    tail: Slot,
+    len: usize,
+}
+
+impl RunQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            links: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            len: 0,
+        }
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub(crate) fn contains(&self, tid: Slot) -> bool {
+        self.links.get(tid).map_or(false, |link| link.queued)
+    }
+    pub(crate) fn front(&self) -> Option<Slot> {
+        if self.head == NIL {
+            None
+        } else {
+            Some(self.head)
+        }
+    }
+    /// does nothing if it is already queued
+    pub(crate) fn push_back(&mut self, tid: Slot) {
+        if self.contains(tid) {
+            return;
+        }
+        if tid >= self.links.len() {
+            self.links.resize(tid + 1, Link::default());
+        }
+        self.links[tid] = Link {
+            prev: self.tail,
+            next: NIL,
+            queued: true,
+        };
+        match self.tail {
+            NIL => self.head = tid,
+            tail => self.links[tail].next = tid,
+        }
+        self.tail = tid;
+        self.len += 1;
+    }
+    pub(crate) fn pop_front(&mut self) -> Option<Slot> {
+        let tid = self.front()?;
+        self.remove(tid);
+        Some(tid)
+    }
+    /// false if it is not queued
+    pub(crate) fn remove(&mut self, tid: Slot) -> bool {
+        if !self.contains(tid) {
+            return false;
+        }
+        let Link { prev, next, .. } = self.links[tid];
+        match prev {
+            NIL => self.head = next,
+            prev => self.links[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.links[next].prev = prev,
+        }
+        self.links[tid] = Link::default();
+        self.len -= 1;
+        true
+    }
+}
+
+impl Default for RunQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// max-heap of task ids by key, equal keys in FIFO order, with amortized O(1) removal of any member
+///
+/// removal is lazy: the entry is only marked stale and skipped once it reaches the top,
+/// and the heap is rebuilt when stale entries outnumber the live ones,
+/// so push and pop stay O(log n) amortized and the heap at most twice the live size
+pub(crate) struct SlotHeap<K: Ord> {
+    /// (key, arrival order, tid)
+    heap: BinaryHeap<(K, Reverse<u64>, Slot)>,
+    /// the arrival order of the live entry of each tid
+    live: Vec<Option<u64>>,
+    seq: u64,
+    stale: usize,
+}
+
+impl<K: Ord + Copy> SlotHeap<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            live: Vec::new(),
+            seq: 0,
+            stale: 0,
+        }
+    }
+    pub(crate) fn contains(&self, tid: Slot) -> bool {
+        self.live.get(tid).map_or(false, Option::is_some)
+    }
+    /// queue it behind the others with the same key, replacing its entry if already queued
+    pub(crate) fn push(&mut self, tid: Slot, key: K) {
+        self.remove(tid);
+        if tid >= self.live.len() {
+            self.live.resize(tid + 1, None);
+        }
+        self.live[tid] = Some(self.seq);
+        self.heap.push((key, Reverse(self.seq), tid));
+        self.seq += 1;
+    }
+    /// the largest key, without taking it out
+    pub(crate) fn peek(&mut self) -> Option<(K, Slot)> {
+        self.skip_stale();
+        self.heap.peek().map(|&(key, _, tid)| (key, tid))
+    }
+    pub(crate) fn pop(&mut self) -> Option<(K, Slot)> {
+        self.skip_stale();
+        let (key, _, tid) = self.heap.pop()?;
+        self.live[tid] = None;
+        Some((key, tid))
+    }
+    /// false if it is not queued
+    pub(crate) fn remove(&mut self, tid: Slot) -> bool {
+        if !self.contains(tid) {
+            return false;
+        }
+        self.live[tid] = None;
+        self.stale += 1;
+        if self.stale > self.heap.len() / 2 {
+            let heap = core::mem::replace(&mut self.heap, BinaryHeap::new());
+            let live = &self.live;
+            self.heap = heap
+                .into_vec()
+                .into_iter()
+                .filter(|&(_, Reverse(seq), tid)| live[tid] == Some(seq))
+                .collect();
+            self.stale = 0;
+        }
+        true
+    }
+    fn skip_stale(&mut self) {
+        while let Some(&(_, Reverse(seq), tid)) = self.heap.peek() {
+            if self.live[tid] == Some(seq) {
+                break;
+            }
+            self.heap.pop();
+            self.stale -= 1;
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::config::*;
+use crate::thread::sched::queue::RunQueue;
+use crate::thread::sched::{Scheduler, Slot};
+
+/// round robin scheduler, first come first serve
+///
+/// the running thread stays at the head of the queue until it yields
+pub(crate) struct RRScheduler {
+    queue: RunQueue,
+    /// ticks left for the head of the queue
+    time: usize,
+}
+impl Scheduler for RRScheduler {
+    // add a new thread for scheduling
+    fn push(&mut self, tid: Slot) {
+        self.queue.push_back(tid)
+    }
+    // pick the head node to schedule if not empty
+    fn pick(&mut self) -> Option<Slot> {
+        self.queue.front()
+    }
+    // give up resources
+    fn r#yield(&mut self, tid: Slot) {
+        if self.queue.remove(tid) {
+            // back of the queue with a fresh time slice
+            self.queue.push_back(tid);
+            self.time = TICKS_PER_TIME_SLICE;
+        }
+    }
+    fn tick(&mut self) -> bool {
+        if self.queue.is_empty() {
+            return false;
+        }
+        self.time -= 1;
+        if self.time == 0 {
+            self.time = TICKS_PER_TIME_SLICE;
+            true
+        } else {
+            false
+        }
+    }
+    fn exit(&mut self, tid: Slot) {
+        let head = self.queue.front() == Some(tid);
+        if self.queue.remove(tid) && head {
+            // the next one starts with a fresh time slice
+            self.time = TICKS_PER_TIME_SLICE;
+        }
+    }
+}
+impl RRScheduler {
+    pub(crate) fn new() -> Self {
+        Self {
+            queue: RunQueue::new(),
+            time: TICKS_PER_TIME_SLICE,
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::config::*;
+use crate::thread::sched::queue::SlotHeap;
+use crate::thread::sched::{Scheduler, Slot};
+use alloc::vec::Vec;
+use core::cmp::{max, Reverse};
+
+/// the stride of a thread with priority 1
+const BIG_STRIDE: u64 = 1 << 20;
+
+#[derive(Copy, Clone)]
+struct StrideInfo {
+    pass: u64,
+    stride: u64,
+}
+impl Default for StrideInfo {
+    fn default() -> Self {
+        Self {
+            pass: 0,
+            stride: BIG_STRIDE / DEFAULT_PRIORITY as u64,
+        }
+    }
+}
+
+/// stride scheduler, each thread gets a share of the CPU proportional to its priority
+///
+/// the thread with the smallest pass runs next, and its pass advances by its stride
+/// (inversely proportional to the priority) every time it gives up the CPU
+pub(crate) struct StrideScheduler {
+    /// ready threads, the smallest pass first
+    queue: SlotHeap<Reverse<u64>>,
+    info: Vec<StrideInfo>,
+    current: Option<Slot>,
+    /// ticks left for the current thread
+    time: usize,
+}
+impl Scheduler for StrideScheduler {
+    fn push(&mut self, tid: Slot) {
+        // start from where the others are, so that a newcomer or a thread
+        // that has been sleeping cannot monopolize the CPU to catch up
+        let min = self.min_pass();
+        let info = self.info(tid);
+        info.pass = max(info.pass, min);
+        let pass = info.pass;
+        self.queue.push(tid, Reverse(pass));
+    }
+    fn pick(&mut self) -> Option<Slot> {
+        self.queue.pop().map(|(_, tid)| {
+            self.current = Some(tid);
+            self.time = TICKS_PER_TIME_SLICE;
+            tid
+        })
+    }
+    fn r#yield(&mut self, tid: Slot) {
+        if self.current == Some(tid) {
+            self.current = None;
+            let info = self.info(tid);
+            info.pass += info.stride;
+            let pass = info.pass;
+            self.queue.push(tid, Reverse(pass));
+        }
+    }
+    fn tick(&mut self) -> bool {
+        if self.current.is_some() && self.time > 0 {
+            self.time -= 1;
+        }
+        self.current.is_some() && self.time == 0
+    }
+    fn exit(&mut self, tid: Slot) {
+        self.remove(tid);
+        // the id will be reused by somebody else
+        *self.info(tid) = StrideInfo::default();
+    }
+    fn sleep(&mut self, tid: Slot) {
+        self.remove(tid);
+    }
+    fn set_priority(&mut self, tid: Slot, prio: usize) {
+        self.info(tid).stride = BIG_STRIDE / max(prio, 1) as u64;
+    }
+}
+impl StrideScheduler {
+    pub(crate) fn new() -> Self {
+        Self {
+            queue: SlotHeap::new(),
+            info: Vec::new(),
+            current: None,
+            time: 0,
+        }
+    }
+    fn info(&mut self, tid: Slot) -> &mut StrideInfo {
+        if tid >= self.info.len() {
+            self.info.resize_with(tid + 1, Default::default);
+        }
+        &mut self.info[tid]
+    }
+    fn min_pass(&mut self) -> u64 {
+        let queued = self.queue.peek().map(|(Reverse(pass), _)| pass);
+        let running = self.current.map(|tid| self.info[tid].pass);
+        match (queued, running) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b).unwrap_or(0),
+        }
+    }
+    /// take a thread off the CPU or out of the queue
+    fn remove(&mut self, tid: Slot) {
+        if self.current == Some(tid) {
+            self.current = None;
+        } else {
+            self.queue.remove(tid);
+        }
+    }
+}
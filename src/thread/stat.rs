@@ -0,0 +1,87 @@
+use crate::config::MAX_HARTS;
+use crate::cpu::PerCpu;
+use crate::time::{Duration, Instant};
+use core::cell::Cell;
+
+const ZERO: Duration = Duration::from_secs(0);
+
+/// scheduler statistics of one thread
+#[derive(Debug, Copy, Clone)]
+pub struct ThreadStat {
+    /// time spent on the CPU
+    pub run: Duration,
+    /// time spent ready to run but waiting for the CPU
+    pub wait: Duration,
+    /// number of times it has been switched in
+    pub switches: u64,
+    /// number of times it has been switched out because its time slice was used up
+    pub preemptions: u64,
+}
+
+impl ThreadStat {
+    pub(crate) const fn new() -> Self {
+        Self {
+            run: ZERO,
+            wait: ZERO,
+            switches: 0,
+            preemptions: 0,
+        }
+    }
+}
+
+/// scheduler statistics of one hart
+#[derive(Debug, Copy, Clone)]
+pub struct HartStat {
+    /// time spent running threads
+    pub run: Duration,
+    /// time spent in the idle loop, since boot
+    pub idle: Duration,
+    pub switches: u64,
+    pub preemptions: u64,
+}
+
+impl HartStat {
+    const fn new() -> Self {
+        Self {
+            run: ZERO,
+            idle: ZERO,
+            switches: 0,
+            preemptions: 0,
+        }
+    }
+}
+
+// only touched by its own hart with interrupts disabled
+static STATS: PerCpu<Cell<HartStat>> = PerCpu::new([IDLE; MAX_HARTS]);
+const IDLE: Cell<HartStat> = Cell::new(HartStat::new());
+
+/// account a thread switched in and back out after running for `run`
+pub(crate) fn record(run: Duration, preempted: bool) {
+    let cell = STATS.get();
+    let mut stat = cell.get();
+    stat.run += run;
+    stat.switches += 1;
+    if preempted {
+        stat.preemptions += 1;
+    }
+    cell.set(stat);
+}
+
+/// a snapshot of the scheduler statistics of `hart`
+pub fn get(hart: usize) -> HartStat {
+    let mut stat = unsafe { STATS.of(hart) }.get();
+    stat.idle = Instant::now()
+        .since_boot()
+        .checked_sub(stat.run)
+        .unwrap_or(ZERO);
+    stat
+}
+
+pub fn dump(hart: usize) {
+    let stat = get(hart);
+    println!("+++ scheduler statistics of hart {} +++", hart);
+    println!(
+        "run {:?}, idle {:?}, {} switches, {} preemptions",
+        stat.run, stat.idle, stat.switches, stat.preemptions
+    );
+}
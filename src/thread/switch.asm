@@ -10,42 +10,35 @@
 
 .macro STORE_ALL
     STORE ra, 0
-    # store satp later
-    STORE s0, 2
-    STORE s1, 3
-    STORE s2, 4
-    STORE s3, 5
-    STORE s4, 6
-    STORE s5, 7
-    STORE s6, 8
-    STORE s7, 9
-    STORE s8, 10
-    STORE s9, 11
-    STORE s10, 12
-    STORE s11, 13
-    csrr s11, satp
-    STORE s11, 1
+    STORE s0, 1
+    STORE s1, 2
+    STORE s2, 3
+    STORE s3, 4
+    STORE s4, 5
+    STORE s5, 6
+    STORE s6, 7
+    STORE s7, 8
+    STORE s8, 9
+    STORE s9, 10
+    STORE s10, 11
+    STORE s11, 12
+    STORE tp, 13
 .endm
 
 .macro LOAD_ALL
-    # first switch to the target thread's address space
-    LOAD s11, 1
-    csrw satp, s11
-    # flush the TLB
-    sfence.vma
-    LOAD s11, 13
-    LOAD s10, 12
-    LOAD s9, 11
-    LOAD s8, 10
-    LOAD s7, 9
-    LOAD s6, 8
-    LOAD s5, 7
-    LOAD s4, 6
-    LOAD s3, 5
-    LOAD s2, 4
-    LOAD s1, 3
-    LOAD s0, 2
-    # satp already load
+    LOAD tp, 13
+    LOAD s11, 12
+    LOAD s10, 11
+    LOAD s9, 10
+    LOAD s8, 9
+    LOAD s7, 8
+    LOAD s6, 7
+    LOAD s5, 6
+    LOAD s4, 5
+    LOAD s3, 4
+    LOAD s2, 3
+    LOAD s1, 2
+    LOAD s0, 1
     LOAD ra, 0
 .endm
     # reserve space for ContextContent (except the trap frame)
@@ -54,10 +47,21 @@
     # old context contains old sp
     sd sp, 0(a0)
     STORE_ALL
-    # switch to the target context
-    # switch the stack first
+    # switch to the target thread's address space
     # a1 points to the new target's context struct,
-    # which contains the new target's stack pointer
+    # which contains its satp next to its stack pointer
+    # kernel threads (satp 0) stay in the current one,
+    # so do threads of the process we are already in
+    ld t0, 1 * XLENB(a1)
+    beqz t0, 1f
+    csrr t1, satp
+    beq t0, t1, 1f
+    csrw satp, t0
+    # flush the TLB
+    sfence.vma
+1:
+    # switch to the target context
+    # switch the stack
     ld sp, 0(a1)
     LOAD_ALL
     # target thread became the `current` thread now
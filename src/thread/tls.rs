@@ -0,0 +1,71 @@
+//! thread-local storage, `#[thread_local]` statics are reached through `tp`
+//!
+//! RISC-V uses TLS variant I with no control block in front:
+//! `tp` points right at the block, a copy of `.tdata` followed by the zeroed `.tbss`
+
+use alloc::alloc::{alloc, dealloc, Layout};
+
+/// `tp` has to be aligned at least as strictly as the TLS segment
+const TLS_ALIGN: usize = 64;
+
+/// where the template lives, see `linker64.ld`
+fn image() -> (usize, usize, usize) {
+    extern "C" {
+        fn stdata();
+        fn etdata();
+        fn etbss();
+    }
+    (stdata as usize, etdata as usize, etbss as usize)
+}
+
+fn layout() -> Option<Layout> {
+    let (start, _, end) = image();
+    match end - start {
+        0 => None,
+        size => Some(Layout::from_size_align(size, TLS_ALIGN).expect("TLS layout illegal")),
+    }
+}
+
+/// the TLS block of one thread
+#[derive(Debug)]
+pub(crate) struct Tls {
+    /// 0 if there are no thread-locals at all, or for the boot thread
+    addr: usize,
+}
+
+impl Tls {
+    /// a fresh block initialized from the template
+    pub(crate) fn new() -> Self {
+        let layout = match layout() {
+            Some(layout) => layout,
+            None => return Self::empty(),
+        };
+        let (tdata, etdata, _) = image();
+        let init = etdata - tdata;
+        unsafe {
+            let addr = alloc(layout);
+            assert!(!addr.is_null(), "out of memory for TLS");
+            core::ptr::copy_nonoverlapping(tdata as *const u8, addr, init);
+            core::ptr::write_bytes(addr.add(init), 0, layout.size() - init);
+            Self {
+                addr: addr as usize,
+            }
+        }
+    }
+    /// thread-locals must not be touched with it
+    pub(crate) const fn empty() -> Self {
+        Self { addr: 0 }
+    }
+    /// the value for `tp`
+    pub(crate) fn tp(&self) -> usize {
+        self.addr
+    }
+}
+
+impl Drop for Tls {
+    fn drop(&mut self) {
+        if let (Some(layout), true) = (layout(), self.addr != 0) {
+            unsafe { dealloc(self.addr as *mut u8, layout) }
+        }
+    }
+}
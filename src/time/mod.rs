@@ -0,0 +1,184 @@
+use crate::config::*;
+use crate::fdt::{self, Fdt};
+use core::convert::TryFrom;
+use core::fmt;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use riscv::register::time;
+
+pub use core::time::Duration;
+
+mod rtc;
+
+/// timer cycles per second
+static mut FREQUENCY: u64 = DEFAULT_TIMEBASE_FREQUENCY;
+/// time since the UNIX epoch at `Instant(0)`, None without a RTC
+static mut EPOCH: Option<Duration> = None;
+
+/// must run before the frame allocator gets a chance to overwrite the device tree
+pub(crate) fn init(dtb: usize) {
+    println!("+++ setting up time +++");
+    let fdt = unsafe { Fdt::new(dtb) };
+    match fdt
+        .as_ref()
+        .and_then(|fdt| fdt.prop("/cpus", "timebase-frequency"))
+        .and_then(fdt::to_int)
+    {
+        Some(freq) => unsafe { FREQUENCY = freq },
+        None => println!(
+            "no timebase-frequency in device tree, assuming {} Hz",
+            frequency()
+        ),
+    }
+    println!("timebase frequency {} Hz", frequency());
+    // #address-cells is 2 on QEMU virt
+    if let Some(reg) = fdt
+        .as_ref()
+        .and_then(|fdt| fdt.compatible_prop("google,goldfish-rtc", "reg"))
+        .filter(|reg| reg.len() >= 8)
+    {
+        rtc::probe(fdt::to_int(&reg[..8]).unwrap() as usize);
+    }
+}
+
+/// read the RTC once, wall-clock time is derived from the monotonic clock after that
+///
+/// the RTC is only mapped after `mem::init`
+pub(crate) fn init_wall() {
+    if let Some(unix) = rtc::read() {
+        unsafe { EPOCH = Some(unix - Instant::now().since_boot()) }
+        println!("+++ wall clock {} +++", DateTime::from(unix));
+    }
+}
+
+/// timer cycles per second
+pub fn frequency() -> u64 {
+    unsafe { FREQUENCY }
+}
+
+/// `u64::MAX` for durations longer than the clock can count
+pub(crate) fn to_cycles(d: Duration) -> u64 {
+    let freq = u128::from(frequency());
+    let cycles = d.as_nanos().saturating_mul(freq) / 1_000_000_000;
+    u64::try_from(cycles).unwrap_or(u64::MAX)
+}
+
+pub(crate) fn from_cycles(cycles: u64) -> Duration {
+    let freq = frequency();
+    let nanos = u128::from(cycles % freq) * 1_000_000_000 / u128::from(freq);
+    Duration::new(cycles / freq, nanos as u32)
+}
+
+/// time since the UNIX epoch, None without a RTC
+pub fn unix() -> Option<Duration> {
+    unsafe { EPOCH }.map(|epoch| epoch + Instant::now().since_boot())
+}
+
+/// a point on the monotonic clock, which counts timer cycles since reset
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Self {
+        Self(time::read64())
+    }
+    pub(crate) fn from_cycles(cycles: u64) -> Self {
+        Self(cycles)
+    }
+    pub(crate) fn cycles(self) -> u64 {
+        self.0
+    }
+    pub fn since_boot(self) -> Duration {
+        from_cycles(self.0)
+    }
+    /// zero if `earlier` is actually later
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        from_cycles(self.0.saturating_sub(earlier.0))
+    }
+    pub fn elapsed(self) -> Duration {
+        Self::now().duration_since(self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// the end of time rather than wrapping around, for a sleep that is practically forever
+    fn add(self, rhs: Duration) -> Self::Output {
+        Self(self.0.saturating_add(to_cycles(rhs)))
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, rhs: Duration) -> Self::Output {
+        Self(self.0.saturating_sub(to_cycles(rhs)))
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Self::Output {
+        self.duration_since(rhs)
+    }
+}
+
+/// calendar date and time in UTC
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u64,
+    pub month: u64,
+    pub day: u64,
+    pub hour: u64,
+    pub minute: u64,
+    pub second: u64,
+}
+
+impl From<Duration> for DateTime {
+    /// from the time since the UNIX epoch
+    ///
+    /// days to civil from http://howardhinnant.github.io/date_algorithms.html
+    fn from(unix: Duration) -> Self {
+        let secs = unix.as_secs();
+        let z = secs / 86400 + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        Self {
+            year,
+            month,
+            day,
+            hour: secs % 86400 / 3600,
+            minute: secs % 3600 / 60,
+            second: secs % 60,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
@@ -0,0 +1,36 @@
+//! Goldfish RTC found on QEMU virt
+//!
+//! https://android.googlesource.com/platform/external/qemu/+/master/docs/GOLDFISH-VIRTUAL-HARDWARE.TXT
+
+use crate::config::*;
+use core::time::Duration;
+
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+/// virtual address of the registers, None if there is no usable RTC
+static mut BASE: Option<usize> = None;
+
+pub(crate) fn probe(paddr: usize) {
+    if paddr != GOLDFISH_RTC_PADDR {
+        println!(
+            "goldfish rtc at {:#x} is not mapped, no wall-clock time",
+            paddr
+        );
+        return;
+    }
+    unsafe { BASE = Some(paddr + PHYSICAL_MEMORY_OFFSET) }
+}
+
+/// time since the UNIX epoch
+pub(crate) fn read() -> Option<Duration> {
+    let base = unsafe { BASE }?;
+    // reading the low half latches the high half
+    let (low, high) = unsafe {
+        (
+            core::ptr::read_volatile((base + TIME_LOW) as *const u32),
+            core::ptr::read_volatile((base + TIME_HIGH) as *const u32),
+        )
+    };
+    Some(Duration::from_nanos(u64::from(high) << 32 | u64::from(low)))
+}
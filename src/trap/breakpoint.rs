@@ -0,0 +1,172 @@
+use crate::sync::SpinMutex;
+use crate::trap::insn::{Insn, C_EBREAK};
+use crate::trap::Frame;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// invoked with the trap frame of whoever hit the breakpoint
+///
+/// leaving `sepc` untouched resumes at the original instruction,
+/// changing it resumes there instead
+pub(crate) type Callback = fn(&mut Frame);
+
+static BREAKPOINTS: SpinMutex<Vec<Breakpoint>> = SpinMutex::new(Vec::new());
+
+#[derive(Copy, Clone)]
+struct Breakpoint {
+    addr: usize,
+    /// the halfword replaced by `c.ebreak`
+    orig: u16,
+    armed: bool,
+    /// None for the temporary breakpoint used to step over an instruction
+    callback: Option<Callback>,
+    /// the breakpoint that has just been stepped over and is waiting for this one to re-arm it
+    rearm: Option<usize>,
+}
+
+impl Breakpoint {
+    fn new(addr: usize, callback: Option<Callback>) -> Self {
+        Self {
+            addr,
+            orig: 0,
+            armed: false,
+            callback,
+            rearm: None,
+        }
+    }
+    unsafe fn arm(&mut self) {
+        if !self.armed {
+            self.orig = core::ptr::read_volatile(self.addr as *const u16);
+            // `c.ebreak` fits in the first halfword of any instruction
+            crate::mem::patch_text(self.addr, &C_EBREAK.to_le_bytes());
+            self.armed = true;
+        }
+    }
+    unsafe fn disarm(&mut self) {
+        if self.armed {
+            crate::mem::patch_text(self.addr, &self.orig.to_le_bytes());
+            self.armed = false;
+        }
+    }
+}
+
+/// place a software breakpoint at `addr`, replacing the callback if one is already there
+///
+/// can only be used after `mem::init`, since the kernel text is patched through the active page table
+pub(crate) fn set(addr: usize, callback: Callback) {
+    let mut bps = BREAKPOINTS.lock();
+    match bps.iter_mut().find(|bp| bp.addr == addr) {
+        Some(bp) => bp.callback = Some(callback),
+        None => bps.push(Breakpoint::new(addr, Some(callback))),
+    }
+    let bp = bps
+        .iter_mut()
+        .find(|bp| bp.addr == addr)
+        .expect("I just put it in there, this should not happen!");
+    unsafe { bp.arm() }
+}
+
+/// remove the breakpoint at `addr` and restore the original instruction
+pub(crate) fn remove(addr: usize) -> bool {
+    let mut bps = BREAKPOINTS.lock();
+    let idx = match bps
+        .iter()
+        .position(|bp| bp.addr == addr && bp.callback.is_some())
+    {
+        Some(idx) => idx,
+        None => return false,
+    };
+    let mut bp = bps.remove(idx);
+    unsafe { bp.disarm() };
+    // someone is waiting for us to re-arm it
+    if let Some(prev) = bp.rearm {
+        if let Some(prev) = bps.iter_mut().find(|bp| bp.addr == prev) {
+            unsafe { prev.arm() }
+        }
+    }
+    true
+}
+
+/// handle a breakpoint exception, false for an `ebreak` that was not placed by us
+pub(crate) fn handle(tf: &mut Frame) -> bool {
+    let addr = tf.sepc;
+    let mut bps = BREAKPOINTS.lock();
+    let idx = match bps.iter().position(|bp| bp.addr == addr && bp.armed) {
+        Some(idx) => idx,
+        // removed after the trap was taken, resume at the original instruction put back
+        None => return !unsafe { Insn::fetch(addr) }.map_or(false, Insn::is_ebreak),
+    };
+    // the previous instruction has been executed, its breakpoint can be put back
+    if let Some(prev) = bps[idx].rearm.take() {
+        if let Some(prev) = bps.iter_mut().find(|bp| bp.addr == prev) {
+            unsafe { prev.arm() }
+        }
+    }
+    let callback = match bps[idx].callback {
+        Some(callback) => callback,
+        None => {
+            // a temporary one, its job is done
+            let mut bp = bps.remove(idx);
+            unsafe { bp.disarm() };
+            return true;
+        }
+    };
+    // the callback is free to set or remove breakpoints
+    drop(bps);
+    callback(tf);
+    if tf.sepc != addr {
+        return true;
+    }
+    // resume at the original instruction:
+    // put it back and catch the next one to re-arm this breakpoint
+    let mut bps = BREAKPOINTS.lock();
+    if let Some(bp) = bps.iter_mut().find(|bp| bp.addr == addr) {
+        unsafe { bp.disarm() };
+        let next = match unsafe { Insn::fetch(addr) } {
+            Some(insn) if !insn.is_control_transfer() => addr + insn.len(),
+            // we cannot tell where a control transfer goes without emulating it,
+            // and a reserved encoding traps as soon as it is run
+            _ => {
+                println!(
+                    "breakpoint at {:#x} disarmed, cannot step over a control transfer",
+                    addr
+                );
+                return true;
+            }
+        };
+        match bps.iter_mut().find(|bp| bp.addr == next) {
+            Some(bp) if bp.armed => bp.rearm = Some(addr),
+            Some(_) => println!(
+                "breakpoint at {:#x} disarmed, the next instruction has a disabled one",
+                addr
+            ),
+            None => {
+                let mut step = Breakpoint::new(next, None);
+                step.rearm = Some(addr);
+                unsafe { step.arm() };
+                bps.push(step);
+            }
+        }
+    }
+    true
+}
+
+pub(crate) fn test() {
+    static HITS: AtomicUsize = AtomicUsize::new(0);
+    #[inline(never)]
+    fn target(x: &usize) -> usize {
+        unsafe { core::ptr::read_volatile(x) * 3 }
+    }
+    fn count(_tf: &mut Frame) {
+        HITS.fetch_add(1, Ordering::SeqCst);
+    }
+    set(target as usize, count);
+    for i in 0..3 {
+        assert_eq!(target(&i), i * 3);
+    }
+    assert_eq!(HITS.load(Ordering::SeqCst), 3);
+    assert!(remove(target as usize));
+    assert_eq!(target(&1), 3);
+    assert_eq!(HITS.load(Ordering::SeqCst), 3);
+    println!("breakpoint test passed");
+}
@@ -0,0 +1,97 @@
+use bit_field::BitField;
+
+/// `c.ebreak`, the compressed form of `ebreak`
+pub(crate) const C_EBREAK: u16 = 0x9002;
+/// `ebreak`, the full 4-byte form
+pub(crate) const EBREAK: u32 = 0x0010_0073;
+
+/// a decoded instruction, just enough for the trap handlers to skip or step over it
+///
+/// only the lowest 32 bits are kept, 48/64-bit encodings are recognized by length only
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Insn {
+    bits: u32,
+    len: usize,
+}
+
+impl Insn {
+    /// decode the instruction located at `addr`, None for a reserved length encoding
+    ///
+    /// instructions are only 2-byte aligned with the C extension,
+    /// so read one halfword at a time to avoid crossing into an unmapped page
+    pub(crate) unsafe fn fetch(addr: usize) -> Option<Self> {
+        let low = core::ptr::read_volatile(addr as *const u16);
+        let len = len_of(low)?;
+        let bits = if len == 2 {
+            u32::from(low)
+        } else {
+            let high = core::ptr::read_volatile((addr + 2) as *const u16);
+            u32::from(low) | u32::from(high) << 16
+        };
+        Some(Self { bits, len })
+    }
+    /// length in bytes
+    pub(crate) fn len(self) -> usize {
+        self.len
+    }
+    pub(crate) fn is_ebreak(self) -> bool {
+        match self.len {
+            2 => self.bits == u32::from(C_EBREAK),
+            4 => self.bits == EBREAK,
+            _ => false,
+        }
+    }
+    /// whether the next pc may be something other than `pc + len`
+    ///
+    /// covers branches, jumps and the SYSTEM instructions that trap or return
+    pub(crate) fn is_control_transfer(self) -> bool {
+        let bits = self.bits as usize;
+        match self.len {
+            2 => {
+                let quadrant = bits.get_bits(0..2);
+                let funct3 = bits.get_bits(13..16);
+                match (quadrant, funct3) {
+                    // c.jal, only exists on RV32, it is c.addiw on RV64
+                    #[cfg(riscv32)]
+                    (0b01, 0b001) => true,
+                    // c.j, c.beqz, c.bnez
+                    (0b01, 0b101) | (0b01, 0b110) | (0b01, 0b111) => true,
+                    // c.jr, c.jalr and c.ebreak have rs2 == 0
+                    // c.mv and c.add share the same funct3 with rs2 != 0
+                    (0b10, 0b100) => bits.get_bits(2..7) == 0,
+                    _ => false,
+                }
+            }
+            4 => match bits.get_bits(0..7) {
+                // BRANCH, JALR, JAL
+                0b110_0011 | 0b110_0111 | 0b110_1111 => true,
+                // SYSTEM with funct3 == 0: ecall, ebreak, sret, wfi...
+                0b111_0011 => bits.get_bits(12..15) == 0,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// length in bytes of an instruction given its lowest halfword, None if reserved
+///
+/// see "Base Instruction-Length Encoding" in the unprivileged spec
+pub(crate) fn len_of(low: u16) -> Option<usize> {
+    if low & 0b11 != 0b11 {
+        Some(2)
+    } else if low & 0b1_1100 != 0b1_1100 {
+        Some(4)
+    } else if low & 0b11_1111 == 0b01_1111 {
+        Some(6)
+    } else if low & 0b111_1111 == 0b011_1111 {
+        Some(8)
+    } else {
+        None
+    }
+}
+
+/// length in bytes of the instruction at `addr`, None if reserved
+pub(crate) unsafe fn len_at(addr: usize) -> Option<usize> {
+    len_of(core::ptr::read_volatile(addr as *const u16))
+}
@@ -1,4 +1,5 @@
-use crate::config::*;
+use crate::process::signal::{self, Signal};
+use core::fmt;
 use riscv::register::{
     scause::{Exception, Interrupt, Scause, Trap},
     sscratch, sstatus, stvec,
@@ -6,9 +7,14 @@ use riscv::register::{
 
 global_asm!(include_str!("trap.asm"));
 
+pub(crate) mod breakpoint;
+mod insn;
+pub mod stat;
 pub mod timer;
+pub mod watchdog;
 
 #[repr(C)]
+#[derive(Clone)]
 pub struct Frame {
     /// General registers
     pub x: [usize; 32],
@@ -22,6 +28,41 @@ pub struct Frame {
     pub scause: Scause,
 }
 
+impl Frame {
+    /// resume at the instruction following the one that trapped
+    ///
+    /// one with a reserved length encoding is tried again, to trap as an illegal instruction
+    pub fn skip(&mut self) {
+        self.sepc += unsafe { insn::len_at(self.sepc) }.unwrap_or(0);
+    }
+    /// trapped from user mode, as told by SPP
+    pub fn from_user(&self) -> bool {
+        self.sstatus & 1 << 8 == 0
+    }
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "sstatus {:#x} sepc {:#x} stval {:#x} scause {:?}",
+            self.sstatus,
+            self.sepc,
+            self.stval,
+            self.scause.cause()
+        )?;
+        for (i, x) in self.x.iter().enumerate() {
+            write!(f, "x{:<2} {:#018x}", i, x)?;
+            if i % 4 == 3 {
+                writeln!(f)?;
+            } else {
+                write!(f, " ")?;
+            }
+        }
+        Ok(())
+    }
+}
+
 pub fn init() {
     println!("+++ setting up trap handler +++");
     unsafe {
@@ -33,6 +74,8 @@ pub fn init() {
         //  if interrupted from S-Mode, sscratch is always 0
         sscratch::write(0);
         stvec::write(__alltraps as usize, stvec::TrapMode::Direct);
+        // let the kernel reach into user memory on behalf of system calls
+        sstatus::set_sum();
         sstatus::set_sie();
     }
 }
@@ -47,38 +90,81 @@ extern "C" fn rust_trap(tf: &mut Frame) {
     //     tf.scause.bits(),
     //     tf.stval
     // );
-    match tf.scause.cause() {
+    let begin = stat::cycles();
+    let cause = tf.scause.cause();
+    match cause {
         Trap::Exception(Exception::Breakpoint) => breakpoint(tf),
-        Trap::Interrupt(Interrupt::SupervisorTimer) => stimer(),
+        Trap::Interrupt(Interrupt::SupervisorTimer) => stimer(tf),
         Trap::Exception(Exception::InstructionPageFault) => page_fault(tf),
         Trap::Exception(Exception::LoadPageFault) => page_fault(tf),
         Trap::Exception(Exception::StorePageFault) => page_fault(tf),
+        Trap::Exception(Exception::UserEnvCall) => crate::syscall::handle(tf),
+        Trap::Exception(e) if tf.from_user() => user_fault(tf, fault_signal(e)),
         _ => panic!("+++ unhandled trap +++"),
     }
+    stat::record(cause.into(), begin);
+    // may switch to another thread, keep it out of the handler time
+    if timer::take_tick() {
+        crate::thread::tick();
+    }
     // println!("returning from timer rust_trap");
 }
 
+/// last stop before `sret`, also for threads entering user mode the first time
+#[no_mangle]
+extern "C" fn rust_trapret(tf: &mut Frame) {
+    if tf.from_user() {
+        signal::deliver(tf);
+    }
+}
+
+/// what user mode gets for an exception other than a system call, page fault or breakpoint
+fn fault_signal(e: Exception) -> Signal {
+    match e {
+        Exception::IllegalInstruction => signal::SIGILL,
+        Exception::InstructionFault | Exception::LoadFault | Exception::StoreFault => {
+            signal::SIGSEGV
+        }
+        // misaligned accesses
+        _ => signal::SIGBUS,
+    }
+}
+
+/// the current thread did something wrong in user mode, it takes `sig` before going back
+fn user_fault(tf: &Frame, sig: Signal) {
+    println!(
+        "{:?} in user mode at {:#x}, stval = {:#x}, signal {}",
+        tf.scause.cause(),
+        tf.sepc,
+        tf.stval,
+        sig
+    );
+    signal::force(sig);
+}
+
 fn breakpoint(tf: &mut Frame) {
+    if tf.from_user() {
+        return user_fault(tf, signal::SIGTRAP);
+    }
+    if breakpoint::handle(tf) {
+        return;
+    }
     println!("a breakpoint set at {:#x}", tf.sepc);
     // points to the next instruction
-    // C extension compact some instructions
-    tf.sepc += 2;
+    // either `ebreak` or `c.ebreak` depending on the assembler
+    tf.skip();
 }
 
-fn stimer() {
-    unsafe {
-        timer::TICKS += 1;
-        if timer::TICKS == 1000 {
-            println!("+++ {} ticks +++", timer::TICKS);
-            timer::TICKS = 0;
-        }
-    }
-    timer::set(TIMEBASE);
-    crate::thread::tick();
+fn stimer(tf: &mut Frame) {
+    timer::interrupt();
+    watchdog::check(tf);
     // println!("returning from timer interrupt");
 }
 
 fn page_fault(tf: &mut Frame) {
+    if tf.from_user() {
+        return user_fault(tf, signal::SIGSEGV);
+    }
     println!(
         "{:?} va = {:#x} instruction = {:#x}",
         tf.scause.cause(),
@@ -88,16 +174,23 @@ fn page_fault(tf: &mut Frame) {
     panic!("page fault!");
 }
 
+/// disable interrupts, returns whether they were enabled for `restore`
 #[inline(always)]
 pub(crate) fn disable() -> usize {
     let mut sstatus: usize;
-    unsafe { asm!("csrci sstatus, 1 << 1":"=r"(sstatus):::"volatile") }
-    sstatus
+    unsafe { asm!("csrrci $0, sstatus, 1 << 1":"=r"(sstatus):::"volatile") }
+    // only SIE is to be restored, the other bits may have changed meanwhile
+    sstatus & 1 << 1
 }
 #[inline(always)]
 pub(crate) fn restore(flags: usize) {
     unsafe { asm!("csrs sstatus, $0"::"r"(flags)::"volatile") }
 }
+/// whether interrupts are enabled on this hart
+#[inline(always)]
+pub(crate) fn enabled() -> bool {
+    sstatus::read().sie()
+}
 #[inline(always)]
 fn enable() {
     restore(1 << 1);
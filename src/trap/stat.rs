@@ -0,0 +1,140 @@
+use crate::config::MAX_HARTS;
+use crate::cpu::PerCpu;
+use core::cell::Cell;
+use riscv::register::scause::{Exception, Interrupt, Trap};
+
+/// what we keep counters for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Cause {
+    Timer,
+    Software,
+    External,
+    PageFault,
+    Syscall,
+    Breakpoint,
+    Other,
+}
+
+const CAUSES: usize = 7;
+
+impl Cause {
+    pub const ALL: [Cause; CAUSES] = [
+        Cause::Timer,
+        Cause::Software,
+        Cause::External,
+        Cause::PageFault,
+        Cause::Syscall,
+        Cause::Breakpoint,
+        Cause::Other,
+    ];
+}
+
+impl From<Trap> for Cause {
+    fn from(trap: Trap) -> Self {
+        match trap {
+            Trap::Interrupt(Interrupt::SupervisorTimer) => Cause::Timer,
+            Trap::Interrupt(Interrupt::SupervisorSoft) => Cause::Software,
+            Trap::Interrupt(Interrupt::SupervisorExternal) => Cause::External,
+            Trap::Exception(Exception::InstructionPageFault)
+            | Trap::Exception(Exception::LoadPageFault)
+            | Trap::Exception(Exception::StorePageFault) => Cause::PageFault,
+            Trap::Exception(Exception::UserEnvCall) => Cause::Syscall,
+            Trap::Exception(Exception::Breakpoint) => Cause::Breakpoint,
+            _ => Cause::Other,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Counter {
+    /// number of traps taken
+    pub count: u64,
+    /// cycles spent in the handlers, thread switches excluded
+    pub cycles: u64,
+}
+
+impl Counter {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            cycles: 0,
+        }
+    }
+}
+
+/// trap statistics of one hart
+#[derive(Debug, Copy, Clone)]
+pub struct Stat {
+    counters: [Counter; CAUSES],
+}
+
+impl Stat {
+    const fn new() -> Self {
+        Self {
+            counters: [Counter::new(); CAUSES],
+        }
+    }
+    pub fn get(&self, cause: Cause) -> Counter {
+        self.counters[cause as usize]
+    }
+    /// traps of all causes added together
+    pub fn total(&self) -> Counter {
+        self.counters
+            .iter()
+            .fold(Counter::default(), |acc, c| Counter {
+                count: acc.count + c.count,
+                cycles: acc.cycles + c.cycles,
+            })
+    }
+}
+
+// only touched by its own hart from inside the trap handler, with interrupts disabled
+static STATS: PerCpu<[Cell<Counter>; CAUSES]> = PerCpu::new([UNUSED; MAX_HARTS]);
+const UNUSED: [Cell<Counter>; CAUSES] = [ZERO; CAUSES];
+const ZERO: Cell<Counter> = Cell::new(Counter::new());
+
+#[inline(always)]
+pub(crate) fn cycles() -> u64 {
+    let cycles: usize;
+    unsafe { asm!("rdcycle $0" : "=r"(cycles) ::: "volatile") }
+    cycles as u64
+}
+
+/// account one trap of `cause` that was entered at cycle `begin`
+pub(crate) fn record(cause: Cause, begin: u64) {
+    let counter = &STATS.get()[cause as usize];
+    let Counter {
+        count,
+        cycles: spent,
+    } = counter.get();
+    counter.set(Counter {
+        count: count + 1,
+        cycles: spent + cycles().wrapping_sub(begin),
+    });
+}
+
+/// a snapshot of the trap statistics of `hart`
+pub fn get(hart: usize) -> Stat {
+    let mut stat = Stat::new();
+    for (counter, cell) in stat.counters.iter_mut().zip(unsafe { STATS.of(hart) }) {
+        *counter = cell.get();
+    }
+    stat
+}
+
+pub fn dump(hart: usize) {
+    let stat = get(hart);
+    println!("+++ trap statistics of hart {} +++", hart);
+    for &cause in Cause::ALL.iter() {
+        let c = stat.get(cause);
+        if c.count != 0 {
+            println!(
+                "{:?}: {} traps, {} cycles, {} on average",
+                cause,
+                c.count,
+                c.cycles,
+                c.cycles / c.count
+            );
+        }
+    }
+}
@@ -1,24 +1,202 @@
 use crate::config::*;
+use crate::cpu::PerCpu;
 use crate::sbi::set_timer;
+use crate::sync::SpinMutex;
+use crate::time::Instant;
+use crate::trap;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::Cell;
 use riscv::register::{sie, time};
 
-pub static mut TICKS: u64 = 0;
+static TICKS: PerCpu<Cell<u64>> = PerCpu::new([ZERO; MAX_HARTS]);
+const ZERO: Cell<u64> = Cell::new(0);
+/// the scheduler tick has fired since the last `take_tick`
+static TICK: PerCpu<Cell<bool>> = PerCpu::new([UNTICKED; MAX_HARTS]);
+const UNTICKED: Cell<bool> = Cell::new(false);
+/// the periodic scheduler tick, None while stopped
+static TICK_TIMER: PerCpu<Cell<Option<TimerId>>> = PerCpu::new([STOPPED; MAX_HARTS]);
+const STOPPED: Cell<Option<TimerId>> = Cell::new(None);
+
+pub type TimerId = usize;
+/// runs in interrupt context, with interrupts disabled
+pub type Callback = Box<dyn FnMut() + Send>;
+
+struct Timer {
+    id: TimerId,
+    deadline: u64,
+    /// 0 for one-shot timers
+    period: u64,
+    callback: Callback,
+}
+
+struct Queue {
+    /// sorted by deadline, the earliest one at the back
+    timers: Vec<Timer>,
+    next_id: TimerId,
+    /// the one whose callback is running, None if it has been cancelled meanwhile
+    running: Option<TimerId>,
+}
+
+impl Queue {
+    const fn new() -> Self {
+        Self {
+            timers: Vec::new(),
+            next_id: 0,
+            running: None,
+        }
+    }
+    fn insert(&mut self, timer: Timer) {
+        // in front of the ones with the same deadline, so that they fire in insertion order
+        let idx = self
+            .timers
+            .iter()
+            .position(|t| t.deadline <= timer.deadline)
+            .unwrap_or_else(|| self.timers.len());
+        self.timers.insert(idx, timer);
+    }
+    fn pop_expired(&mut self, now: u64) -> Option<Timer> {
+        match self.timers.last() {
+            Some(t) if t.deadline <= now => self.timers.pop(),
+            _ => None,
+        }
+    }
+    /// program the hardware timer for the earliest deadline
+    fn program(&self) {
+        set_timer(self.timers.last().map_or(u64::max_value(), |t| t.deadline));
+    }
+}
+
+static QUEUE: SpinMutex<Queue> = SpinMutex::new(Queue::new());
 
 pub fn init() {
     println!("+++ setting up timer +++");
-    unsafe {
-        TICKS = 0;
-        sie::set_stimer();
-    }
+    TICKS.get().set(0);
+    unsafe { sie::set_stimer() };
     // only after setting the timer for the first time
     // the machine timer interrupt bit would be set (mie[7])
-    set(TIMEBASE);
+    start_tick();
 }
 
-pub fn set(delta: u64) {
-    set_timer(get() + delta);
+/// (re)start the periodic scheduler tick, which is just one of the timers
+pub(crate) fn start_tick() {
+    let timer = TICK_TIMER.get();
+    if timer.get().is_none() {
+        super::watchdog::reset();
+        timer.set(Some(every(
+            crate::time::frequency() / TICKS_PER_SECOND,
+            tick,
+        )));
+    }
+}
+
+/// stop the periodic scheduler tick, other timers keep firing on demand
+pub(crate) fn stop_tick() {
+    if let Some(id) = TICK_TIMER.get().take() {
+        super::watchdog::pause();
+        cancel(id);
+    }
+}
+
+/// fire `callback` at `deadline`, then every `period` after that unless `period` is 0
+pub fn add(deadline: u64, period: u64, callback: impl FnMut() + Send + 'static) -> TimerId {
+    // the queue is also taken from the timer interrupt
+    let flags = trap::disable();
+    let mut queue = QUEUE.lock();
+    let id = queue.next_id;
+    queue.next_id += 1;
+    queue.insert(Timer {
+        id,
+        deadline,
+        period,
+        callback: Box::new(callback),
+    });
+    queue.program();
+    drop(queue);
+    trap::restore(flags);
+    id
+}
+
+/// fire `callback` once at `deadline`
+pub fn at(deadline: Instant, callback: impl FnMut() + Send + 'static) -> TimerId {
+    add(deadline.cycles(), 0, callback)
+}
+
+/// fire `callback` once, `delta` timer cycles from now
+pub fn after(delta: u64, callback: impl FnMut() + Send + 'static) -> TimerId {
+    add(get() + delta, 0, callback)
+}
+
+/// fire `callback` every `period` timer cycles
+pub fn every(period: u64, callback: impl FnMut() + Send + 'static) -> TimerId {
+    add(get() + period, period, callback)
+}
+
+/// stop a timer from firing again, false if it is not pending
+pub fn cancel(id: TimerId) -> bool {
+    let flags = trap::disable();
+    let mut queue = QUEUE.lock();
+    let found = if queue.running == Some(id) {
+        queue.running = None;
+        true
+    } else if let Some(idx) = queue.timers.iter().position(|t| t.id == id) {
+        queue.timers.remove(idx);
+        queue.program();
+        true
+    } else {
+        false
+    };
+    drop(queue);
+    trap::restore(flags);
+    found
+}
+
+/// run the callbacks of all expired timers, then re-arm for the next deadline
+pub(crate) fn interrupt() {
+    let now = get();
+    loop {
+        let mut timer = {
+            let mut queue = QUEUE.lock();
+            match queue.pop_expired(now) {
+                Some(timer) => {
+                    queue.running = Some(timer.id);
+                    timer
+                }
+                None => break,
+            }
+        };
+        // the callback is free to add or cancel timers
+        (timer.callback)();
+        let mut queue = QUEUE.lock();
+        if queue.running.take() == Some(timer.id) && timer.period != 0 {
+            timer.deadline += timer.period;
+            // we have fallen behind, skip the missed periods instead of firing in a burst
+            if timer.deadline <= now {
+                timer.deadline = now + timer.period;
+            }
+            queue.insert(timer);
+        }
+    }
+    QUEUE.lock().program();
+}
+
+/// whether the scheduler tick has fired since the last time we asked
+pub(crate) fn take_tick() -> bool {
+    TICK.get().replace(false)
+}
+
+fn tick() {
+    let ticks = TICKS.get();
+    ticks.set(ticks.get() + 1);
+    if ticks.get() == 1000 {
+        println!("+++ {} ticks +++", ticks.get());
+        super::stat::dump(crate::cpu::id());
+        crate::thread::stat::dump(crate::cpu::id());
+        ticks.set(0);
+    }
+    TICK.get().set(true);
 }
 
-fn get() -> u64 {
+pub(crate) fn get() -> u64 {
     time::read64()
 }
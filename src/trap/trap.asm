@@ -75,6 +75,11 @@ from_user:
     STORE s2, 33
     STORE s3, 34
     STORE s4, 35
+    # from user: tp is the user's, the kernel's own was left right above the trap frame
+    andi s0, s1, 1 << 8
+    bnez s0, 1f
+    LOAD tp, 36
+1:
 .endm
 
 
@@ -91,6 +96,8 @@ to_user:
     addi s0, sp, 36 * XLENB
     # if we came from U-Mode, sscratch contains user stack address
     csrw sscratch, s0
+    # keep the kernel's tp for the next trap, there is room above, see `new_uthread`
+    sd tp, 0(s0)
 to_kernel:
     # if we came from S-Mode, sscratch contains 0
     # sret would return to the privilege level set in sstatus.SPP
@@ -140,6 +147,9 @@ __alltraps:
 
 	.global __trapret
 __trapret:
+	# signals are taken on the way back to user mode
+	mv a0, sp
+	call rust_trapret
 	LOAD_ALL
 	sret
 
@@ -0,0 +1,138 @@
+use crate::config::*;
+use crate::cpu::PerCpu;
+use crate::time::{self, Duration};
+use crate::trap::{timer, Frame};
+use core::cell::Cell;
+
+#[derive(Copy, Clone)]
+struct Hart {
+    online: bool,
+    /// the periodic tick is running, without it there is nothing to expect
+    ticking: bool,
+    /// time of the last timer interrupt
+    last_tick: u64,
+    /// time the idle loop last got the CPU back
+    last_sched: u64,
+    /// the current stall has already been reported
+    reported: bool,
+    /// bit `n` is set once hart `n` has been reported for not ticking, until it ticks again
+    stalled: usize,
+}
+
+impl Hart {
+    const fn new() -> Self {
+        Self {
+            online: false,
+            ticking: false,
+            last_tick: 0,
+            last_sched: 0,
+            reported: false,
+            stalled: 0,
+        }
+    }
+}
+
+// only touched from the timer interrupt and the idle loop of its own hart, others just look
+static HARTS: PerCpu<Cell<Hart>> = PerCpu::new([OFFLINE; MAX_HARTS]);
+const OFFLINE: Cell<Hart> = Cell::new(Hart::new());
+/// zero disables the watchdog
+static mut TIMEOUT: Duration = Duration::from_millis(WATCHDOG_TIMEOUT_MS);
+
+/// change the window a hart may go without ticking or rescheduling
+///
+/// zero turns the watchdog off
+pub fn set_timeout(timeout: Duration) {
+    unsafe { TIMEOUT = timeout }
+}
+
+/// start watching the current hart afresh, the periodic tick has just been started
+pub(crate) fn reset() {
+    let now = timer::get();
+    HARTS.get().set(Hart {
+        online: true,
+        ticking: true,
+        last_tick: now,
+        last_sched: now,
+        reported: false,
+        stalled: 0,
+    })
+}
+
+/// the periodic tick has been stopped, long gaps between interrupts are expected
+pub(crate) fn pause() {
+    let hart = HARTS.get();
+    hart.set(Hart {
+        ticking: false,
+        ..hart.get()
+    })
+}
+
+/// the idle loop is running, so the scheduler is making progress
+pub(crate) fn touch() {
+    let hart = HARTS.get();
+    hart.set(Hart {
+        last_sched: timer::get(),
+        reported: false,
+        ..hart.get()
+    })
+}
+
+/// called on every timer interrupt with the frame of whatever it interrupted
+pub(crate) fn check(tf: &Frame) {
+    let hart = HARTS.get();
+    let mut me = hart.get();
+    watch(&mut me, tf);
+    hart.set(me);
+}
+
+fn watch(me: &mut Hart, tf: &Frame) {
+    let timeout = time::to_cycles(unsafe { TIMEOUT });
+    let now = timer::get();
+    let id = crate::cpu::id();
+    let last_tick = core::mem::replace(&mut me.last_tick, now);
+    if timeout == 0 || !me.ticking {
+        return;
+    }
+    // we cannot interrupt ourselves while interrupts are off,
+    // but at least we can tell where they have been turned on again
+    if now - last_tick > timeout {
+        println!(
+            "+++ watchdog: hart {} did not tick for {} cycles +++",
+            id,
+            now - last_tick
+        );
+        report(tf);
+    }
+    if !me.reported && now - me.last_sched > timeout {
+        me.reported = true;
+        println!(
+            "+++ watchdog: soft lockup on hart {}, thread {:?} did not reschedule for {} cycles +++",
+            id,
+            crate::thread::current(),
+            now - me.last_sched
+        );
+        report(tf);
+    }
+    for other in (0..MAX_HARTS).filter(|&other| other != id) {
+        // a copy, it may be changing meanwhile
+        let hart = unsafe { HARTS.of(other) }.get();
+        let silent = now.saturating_sub(hart.last_tick);
+        if !hart.online || silent <= timeout {
+            me.stalled &= !(1 << other);
+        } else if me.stalled & 1 << other == 0 {
+            me.stalled |= 1 << other;
+            println!(
+                "+++ watchdog: hart {} did not tick for {} cycles, interrupts disabled? +++",
+                other, silent
+            );
+        }
+    }
+}
+
+fn report(tf: &Frame) {
+    println!("{:?}", tf);
+    // a user frame pointer has nothing to tell about the kernel
+    if !tf.from_user() {
+        crate::backtrace::print(tf.x[8]);
+    }
+}